@@ -0,0 +1,239 @@
+extern crate flang;
+
+use flang::lang::diagnostics::{English, Messages};
+use flang::lang::diff::token_diff;
+use flang::lang::scanner::{tokenize, Token, TokenPosition};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+
+const USAGE: &str = "usage:
+    flang lex [--json] [--positions] [--color] [<file>]
+    flang token-diff <a.fl> <b.fl>
+
+lex reads from stdin when no file or '-' is given";
+
+/// Prints the usage and exits with an error
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Opens a file for reading, or stdin for `-`
+fn open(path: &str) -> Box<dyn BufRead> {
+    if path == "-" {
+        return Box::new(BufReader::new(io::stdin()));
+    }
+
+    match File::open(path) {
+        Ok(file) => Box::new(BufReader::new(file)),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Tokenizes a file, printing any scanner errors and exiting on failure
+fn tokenize_file(path: &str, messages: &dyn Messages) -> Vec<(TokenPosition, Token)> {
+    tokenize(&mut open(path)).unwrap_or_else(|errors| {
+        for (pos, err) in errors {
+            eprintln!("{}: {} at {}", path, err.localized(messages), pos);
+        }
+        process::exit(2);
+    })
+}
+
+/// Prints one side of a token diff
+fn print_side(path: &str, side: Option<&(TokenPosition, Token)>) {
+    match side {
+        Some((pos, token)) => println!("  {}: {:?} at {}", path, token, pos),
+        None => println!("  {}: end of file", path),
+    }
+}
+
+/// Compares two files at the token level
+///
+/// Exits with 0 if the files are token-equivalent and 1 otherwise
+fn token_diff_command(a: &str, b: &str, messages: &dyn Messages) {
+    let tokens_a = tokenize_file(a, messages);
+    let tokens_b = tokenize_file(b, messages);
+
+    match token_diff(&tokens_a, &tokens_b) {
+        None => println!("{} and {} are token-equivalent", a, b),
+        Some(diff) => {
+            println!("{} and {} differ at token {}", a, b, diff.index);
+            print_side(a, diff.left);
+            print_side(b, diff.right);
+            process::exit(1);
+        }
+    }
+}
+
+/// Output options of the lex command
+#[derive(Default)]
+struct LexOptions {
+    json: bool,
+    positions: bool,
+    color: bool,
+}
+
+/// Escapes a string for use inside a JSON string literal
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders the payload of a token as a JSON value, if it has one
+fn json_value(token: &Token) -> Option<String> {
+    match token {
+        Token::Int(i) | Token::Char(i) => Some(i.to_string()),
+        Token::Float(f) if f.is_finite() => Some(f.to_string()),
+        Token::Float(_) => Some("null".to_string()),
+        Token::Name(s) | Token::Call(s) | Token::Builtin(s) | Token::Shebang(s) | Token::LineComment(s) => {
+            Some(format!("\"{}\"", json_escape(s)))
+        }
+        _ => None,
+    }
+}
+
+/// Renders a token as a JSON object
+fn json_token(pos: &TokenPosition, token: &Token, positions: bool) -> String {
+    let mut fields = vec![format!("\"kind\":\"{:?}\"", token.kind())];
+    if let Some(value) = json_value(token) {
+        fields.push(format!("\"value\":{}", value));
+    }
+    if positions {
+        fields.push(format!(
+            "\"line\":{},\"position\":{},\"length\":{}",
+            pos.line, pos.position, pos.length
+        ));
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+/// ANSI color code used for a token
+fn color_code(token: &Token) -> &'static str {
+    match token {
+        Token::Int(_) | Token::Float(_) | Token::Char(_) => "33",
+        Token::Name(_) => "37",
+        Token::Call(_) | Token::Builtin(_) => "36",
+        Token::Lambda | Token::Comma | Token::Period | Token::Semicolon | Token::Lpar | Token::Rpar => "35",
+        _ => "32",
+    }
+}
+
+/// Tokenizes a file or stdin and prints the tokens or errors
+fn lex_command(path: &str, options: &LexOptions, messages: &dyn Messages) {
+    let tokens = match tokenize(&mut open(path)) {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            for (pos, err) in errors {
+                let message = err.localized(messages);
+                if options.color {
+                    eprintln!("\x1b[31merror\x1b[0m: {} at {}", message, pos);
+                } else {
+                    eprintln!("error: {} at {}", message, pos);
+                }
+            }
+            process::exit(1);
+        }
+    };
+
+    if options.json {
+        let rendered: Vec<String> = tokens
+            .iter()
+            .map(|(pos, token)| json_token(pos, token, options.positions))
+            .collect();
+        println!("[{}]", rendered.join(","));
+        return;
+    }
+
+    for (pos, token) in &tokens {
+        let token = if options.color {
+            format!("\x1b[{}m{:?}\x1b[0m", color_code(token), token)
+        } else {
+            format!("{:?}", token)
+        };
+
+        if options.positions {
+            println!("{}:{}\t{}", pos.line, pos.position, token);
+        } else {
+            println!("{}", token);
+        }
+    }
+}
+
+/// Parses the arguments of the lex command
+fn lex_args(args: &[&str]) -> (String, LexOptions) {
+    let mut options = LexOptions::default();
+    let mut path = None;
+
+    for arg in args {
+        match *arg {
+            "--json" => options.json = true,
+            "--positions" => options.positions = true,
+            "--color" => options.color = true,
+            flag if flag.starts_with("--") => usage(),
+            file if path.is_none() => path = Some(file.to_string()),
+            _ => usage(),
+        }
+    }
+
+    (path.unwrap_or_else(|| "-".to_string()), options)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let args: Vec<&str> = args.iter().skip(1).map(String::as_str).collect();
+    let messages = English;
+
+    match args[..] {
+        ["lex", ref rest @ ..] => {
+            let (path, options) = lex_args(rest);
+            lex_command(&path, &options, &messages)
+        }
+        ["token-diff", a, b] => token_diff_command(a, b, &messages),
+        _ => usage(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_token() {
+        let tokens = tokenize(&mut "f(x) + 1.5".as_bytes()).unwrap();
+        let rendered: Vec<String> = tokens.iter().map(|(pos, t)| json_token(pos, t, false)).collect();
+        assert_eq!(
+            rendered,
+            vec![
+                "{\"kind\":\"Call\",\"value\":\"f\"}",
+                "{\"kind\":\"Name\",\"value\":\"x\"}",
+                "{\"kind\":\"Rpar\"}",
+                "{\"kind\":\"Add\"}",
+                "{\"kind\":\"Float\",\"value\":1.5}",
+            ]
+        );
+        assert_eq!(
+            json_token(&tokens[0].0, &tokens[0].1, true),
+            "{\"kind\":\"Call\",\"value\":\"f\",\"line\":0,\"position\":0,\"length\":2}"
+        );
+    }
+
+    #[test]
+    fn test_json_escape() {
+        assert_eq!(json_escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+}
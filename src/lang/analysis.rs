@@ -0,0 +1,251 @@
+//! Queries over token streams.
+//!
+//! These work directly on the output of the scanner and are meant for tools
+//! such as dashboards and editors which do not need a full parse.
+
+use super::scanner::{Token, TokenKind, TokenPosition};
+use std::collections::BTreeSet;
+
+/// Collects every token of the given kind together with its position
+pub fn collect_kind(tokens: &[(TokenPosition, Token)], kind: TokenKind) -> Vec<&(TokenPosition, Token)> {
+    tokens.iter().filter(|(_, token)| token.kind() == kind).collect()
+}
+
+/// Returns every distinct identifier used by names, calls and builtins
+pub fn identifiers(tokens: &[(TokenPosition, Token)]) -> BTreeSet<String> {
+    tokens
+        .iter()
+        .filter_map(|(_, token)| match token {
+            Token::Name(s) | Token::Call(s) | Token::Builtin(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns every distinct builtin called, such as `_print`, without the parenthesis
+///
+/// Builtins can only be written as calls, so this is the complete set a program can reach.
+pub fn referenced_builtins(tokens: &[(TokenPosition, Token)]) -> BTreeSet<String> {
+    tokens
+        .iter()
+        .filter_map(|(_, token)| match token {
+            Token::Builtin(s) => Some(s.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Computes a rough cyclomatic complexity score for a token stream
+///
+/// The score is one more than the number of decision points, which are the logical `&&` and `||` operators.
+pub fn complexity(tokens: &[(TokenPosition, Token)]) -> u32 {
+    let decisions = tokens
+        .iter()
+        .filter(|(_, token)| matches!(token, Token::And | Token::Or))
+        .count() as u32;
+
+    decisions + 1
+}
+
+/// Returns the deepest nesting of parentheses, calls and builtins
+///
+/// Unbalanced closing parentheses never bring the depth below zero.
+pub fn max_paren_depth(tokens: &[(TokenPosition, Token)]) -> usize {
+    let mut depth: usize = 0;
+    let mut max = 0;
+
+    for (_, token) in tokens {
+        match token {
+            Token::Lpar | Token::Call(_) | Token::Builtin(_) => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            Token::Rpar => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+
+    max
+}
+
+/// Whether a token opens a parenthesis
+fn is_open(token: &Token) -> bool {
+    matches!(token, Token::Lpar | Token::Call(_) | Token::Builtin(_))
+}
+
+/// Finds the index of the bracket matching the one at `index`
+///
+/// Opening parentheses, calls and builtins are matched with their closing parenthesis and the other way around.
+/// Returns `None` if the token is not a bracket or has no match.
+pub fn matching_bracket(tokens: &[(TokenPosition, Token)], index: usize) -> Option<usize> {
+    let (_, token) = tokens.get(index)?;
+    let mut depth: usize = 0;
+
+    if is_open(token) {
+        for (i, (_, token)) in tokens.iter().enumerate().skip(index + 1) {
+            if is_open(token) {
+                depth += 1;
+            } else if *token == Token::Rpar {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            }
+        }
+    } else if *token == Token::Rpar {
+        for (i, (_, token)) in tokens.iter().enumerate().take(index).rev() {
+            if *token == Token::Rpar {
+                depth += 1;
+            } else if is_open(token) {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the value of every integer and float literal together with its position
+///
+/// A leading minus is a separate `Sub` token, so the values are never negative.
+pub fn numeric_literals(tokens: &[(TokenPosition, Token)]) -> Vec<(TokenPosition, f64)> {
+    tokens
+        .iter()
+        .filter_map(|(pos, token)| match token {
+            Token::Int(i) => Some((pos.clone(), *i as f64)),
+            Token::Float(x) => Some((pos.clone(), *x)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds comments containing one of the given markers, such as `TODO` or `FIXME`
+///
+/// Comments are only present when scanning with `keep_comments`.
+/// Returns the position of each comment, the earliest marker in it, and the text following the marker
+/// with any separating colon and whitespace removed.
+pub fn extract_markers(tokens: &[(TokenPosition, Token)], markers: &[&str]) -> Vec<(TokenPosition, String, String)> {
+    tokens
+        .iter()
+        .filter_map(|(pos, token)| match token {
+            Token::LineComment(text) => markers
+                .iter()
+                .filter_map(|marker| text.find(marker).map(|i| (i, *marker)))
+                .min()
+                .map(|(i, marker)| {
+                    let message = text[i + marker.len()..].trim_start_matches(':').trim();
+                    (pos.clone(), marker.to_string(), message.to_string())
+                }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_collect_kind() {
+        let tokens = tokenize(&mut "f = \\x.x*2 + 1.5\ng(10)".as_bytes()).unwrap();
+        let ints = collect_kind(&tokens, TokenKind::Int);
+        assert_eq!(ints.len(), 2);
+        assert_eq!(ints[0].1, Token::Int(2));
+        assert_eq!(ints[0].0, TokenPosition { line: 0, position: 9, length: 1 });
+        assert_eq!(ints[1].1, Token::Int(10));
+        assert_eq!(ints[1].0, TokenPosition { line: 1, position: 2, length: 2 });
+
+        let calls = collect_kind(&tokens, TokenKind::Call);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].1, Token::Call("g".to_string()));
+
+        assert!(collect_kind(&tokens, TokenKind::Shl).is_empty());
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let tokens = tokenize(&mut "f = \\g x.g(g(x))\ny = f(h, x) + _load(x)".as_bytes()).unwrap();
+        let names: Vec<String> = identifiers(&tokens).into_iter().collect();
+        assert_eq!(names, vec!["_load", "f", "g", "h", "x", "y"]);
+    }
+
+    #[test]
+    fn test_complexity() {
+        let tokens = tokenize(&mut "x = 1 + 2".as_bytes()).unwrap();
+        assert_eq!(complexity(&tokens), 1);
+
+        let tokens = tokenize(&mut "f = \\a,b.(a > 0 && b > 0) || (a < 0 && b < 0)".as_bytes()).unwrap();
+        assert_eq!(complexity(&tokens), 4);
+    }
+
+    #[test]
+    fn test_max_paren_depth() {
+        let tokens = tokenize(&mut "f(g(h(x)))".as_bytes()).unwrap();
+        assert_eq!(max_paren_depth(&tokens), 3);
+
+        let tokens = tokenize(&mut "a + b * c".as_bytes()).unwrap();
+        assert_eq!(max_paren_depth(&tokens), 0);
+
+        let tokens = tokenize(&mut "(a)) + ((b) * _put(c))".as_bytes()).unwrap();
+        assert_eq!(max_paren_depth(&tokens), 2);
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        let tokens = tokenize(&mut "width = 80\nratio = -0.25 * f(3, 'c')".as_bytes()).unwrap();
+        assert_eq!(
+            numeric_literals(&tokens),
+            vec![
+                (TokenPosition { line: 0, position: 8, length: 2 }, 80.0),
+                (TokenPosition { line: 1, position: 9, length: 4 }, 0.25),
+                (TokenPosition { line: 1, position: 18, length: 1 }, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let tokens = tokenize(&mut "f(g(x)) + (y".as_bytes()).unwrap();
+        assert_eq!(matching_bracket(&tokens, 0), Some(4));
+        assert_eq!(matching_bracket(&tokens, 1), Some(3));
+        assert_eq!(matching_bracket(&tokens, 4), Some(0));
+        assert_eq!(matching_bracket(&tokens, 3), Some(1));
+        assert_eq!(matching_bracket(&tokens, 6), None);
+        assert_eq!(matching_bracket(&tokens, 2), None);
+        assert_eq!(matching_bracket(&tokens, 100), None);
+    }
+
+    #[test]
+    fn test_extract_markers() {
+        use crate::lang::scanner::{tokenize_with_config, ScannerConfig};
+
+        let config = ScannerConfig {
+            keep_comments: true,
+            ..ScannerConfig::default()
+        };
+        let source = "# TODO: fix this\nf = \\x.x # FIXME handle zero, TODO later\n# plain comment";
+        let tokens = tokenize_with_config(&mut source.as_bytes(), &config).unwrap();
+        assert_eq!(
+            extract_markers(&tokens, &["TODO", "FIXME"]),
+            vec![
+                (TokenPosition { line: 0, position: 0, length: 16 }, "TODO".to_string(), "fix this".to_string()),
+                (
+                    TokenPosition { line: 1, position: 9, length: 31 },
+                    "FIXME".to_string(),
+                    "handle zero, TODO later".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_referenced_builtins() {
+        let tokens = tokenize(&mut "x = _read(f)\n_print(x + _read(g))\nprint(x)".as_bytes()).unwrap();
+        let builtins: Vec<String> = referenced_builtins(&tokens).into_iter().collect();
+        assert_eq!(builtins, vec!["_print", "_read"]);
+    }
+}
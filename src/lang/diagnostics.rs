@@ -0,0 +1,178 @@
+//! Rendering of scanner errors for humans.
+
+use super::scanner::{ScanError, TokenPosition};
+use std::fmt;
+
+/// A catalog of error messages
+///
+/// Embedders can implement this to translate or reword errors, usually by matching on `ScanError::code`.
+/// Any message which is not overridden falls back to the English text of `Display`.
+pub trait Messages {
+    /// Renders the message of a scanner error, without its position
+    fn scan_error(&self, err: &ScanError) -> String {
+        err.to_string()
+    }
+}
+
+/// The default English messages
+pub struct English;
+
+impl Messages for English {}
+
+/// A scanner error which displays its message from a catalog, see `ScanError::localized`
+pub struct Localized<'a> {
+    err: &'a ScanError,
+    messages: &'a dyn Messages,
+}
+
+impl fmt::Display for Localized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.messages.scan_error(self.err))
+    }
+}
+
+impl ScanError {
+    /// Displays the error with the message from a catalog instead of the English default
+    pub fn localized<'a>(&'a self, messages: &'a dyn Messages) -> Localized<'a> {
+        Localized { err: self, messages }
+    }
+}
+
+/// Number of errors listed by `summarize_errors` before the rest are counted
+const SUMMARY_LIMIT: usize = 3;
+
+/// Renders a list of errors as a single line, suitable for CI logs
+///
+/// Only the first few errors are listed, the rest are counted as "(+K more)".
+pub fn summarize_errors(errors: &[(TokenPosition, ScanError)]) -> String {
+    summarize_errors_with(errors, &English)
+}
+
+/// Renders a list of errors as a single line, see `summarize_errors`, taking messages from a catalog
+pub fn summarize_errors_with(errors: &[(TokenPosition, ScanError)], messages: &dyn Messages) -> String {
+    let listed: Vec<String> = errors
+        .iter()
+        .take(SUMMARY_LIMIT)
+        .map(|(pos, err)| format!("line {}:{} {}", pos.line, pos.position, err.localized(messages)))
+        .collect();
+
+    let mut summary = format!(
+        "{} lexer error{}: {}",
+        errors.len(),
+        if errors.len() == 1 { "" } else { "s" },
+        listed.join("; ")
+    );
+
+    if errors.len() > SUMMARY_LIMIT {
+        summary.push_str(&format!(" (+{} more)", errors.len() - SUMMARY_LIMIT));
+    }
+
+    summary
+}
+
+/// Renders errors grouped by line, showing each line once with a caret under every error
+///
+/// Groups are sorted by line and separated by a blank line. Carets are aligned by characters,
+/// so they stay under the right text on lines with non-ASCII characters.
+pub fn render_grouped_errors(source: &str, errors: &[(TokenPosition, ScanError)]) -> String {
+    render_grouped_errors_with(source, errors, &English)
+}
+
+/// Renders errors grouped by line, see `render_grouped_errors`, taking messages from a catalog
+pub fn render_grouped_errors_with(source: &str, errors: &[(TokenPosition, ScanError)], messages: &dyn Messages) -> String {
+    let mut sorted: Vec<&(TokenPosition, ScanError)> = errors.iter().collect();
+    sorted.sort_by_key(|(pos, _)| (pos.line, pos.position));
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut blocks = Vec::new();
+
+    for group in sorted.chunk_by(|(a, _), (b, _)| a.line == b.line) {
+        let line_num = group[0].0.line;
+        let number = line_num.to_string();
+        let pad = " ".repeat(number.len());
+        let mut block = String::new();
+
+        if let Some(line) = lines.get(line_num) {
+            let mut carets = String::new();
+            for (pos, _) in group {
+                let start = line.get(..pos.position).map_or(0, |prefix| prefix.chars().count());
+                let width = line.get(pos.position..pos.end().position).map_or(1, |text| text.chars().count().max(1));
+                while carets.chars().count() < start {
+                    carets.push(' ');
+                }
+                carets.push_str(&"^".repeat(width));
+            }
+            block.push_str(&format!("{} | {}\n{} | {}\n", number, line, pad, carets));
+        }
+
+        for (pos, err) in group {
+            block.push_str(&format!("{} = {} at position {}\n", pad, err.localized(messages), pos.position));
+        }
+        blocks.push(block);
+    }
+
+    blocks.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_summarize_errors() {
+        let errors = tokenize(&mut "x ¤\n'' + ¤\n¤ ¤".as_bytes()).unwrap_err();
+        assert_eq!(
+            summarize_errors(&errors),
+            "5 lexer errors: line 0:2 unknown token '¤'; line 1:0 empty char literal; line 1:5 unknown token '¤' (+2 more)"
+        );
+
+        let errors = tokenize(&mut "x ¤".as_bytes()).unwrap_err();
+        assert_eq!(summarize_errors(&errors), "1 lexer error: line 0:2 unknown token '¤'");
+    }
+
+    struct Norwegian;
+
+    impl Messages for Norwegian {
+        fn scan_error(&self, err: &ScanError) -> String {
+            match err {
+                ScanError::UnknownToken(s) => format!("ukjent symbol '{}'", s),
+                _ => English.scan_error(err),
+            }
+        }
+    }
+
+    #[test]
+    fn test_messages() {
+        let errors = tokenize(&mut "x ¤ ''".as_bytes()).unwrap_err();
+        assert_eq!(errors[0].1.code(), "E001");
+        assert_eq!(English.scan_error(&errors[0].1), "unknown token '¤'");
+        assert_eq!(Norwegian.scan_error(&errors[0].1), "ukjent symbol '¤'");
+        assert_eq!(Norwegian.scan_error(&errors[1].1), "empty char literal");
+        assert_eq!(format!("{}", errors[0].1.localized(&Norwegian)), "ukjent symbol '¤'");
+        assert_eq!(errors[1].1.localized(&English).to_string(), errors[1].1.to_string());
+    }
+
+    #[test]
+    fn test_render_grouped_errors() {
+        let source = "a = $ + ¤\nb = 1\nc = ''";
+        let errors = tokenize(&mut source.as_bytes()).unwrap_err();
+        assert_eq!(
+            render_grouped_errors(source, &errors),
+            "0 | a = $ + ¤\n  |     ^   ^\n  = unknown token '$' at position 4\n  = unknown token '¤' at position 8\n\n\
+             2 | c = ''\n  |     ^^\n  = empty char literal at position 4\n"
+        );
+    }
+
+    #[test]
+    fn test_rendering_with_messages() {
+        let source = "x ¤\n''";
+        let errors = tokenize(&mut source.as_bytes()).unwrap_err();
+        assert_eq!(
+            summarize_errors_with(&errors, &Norwegian),
+            "2 lexer errors: line 0:2 ukjent symbol '¤'; line 1:0 empty char literal"
+        );
+        assert_eq!(summarize_errors_with(&errors, &English), summarize_errors(&errors));
+        assert!(render_grouped_errors_with(source, &errors, &Norwegian).contains("= ukjent symbol '¤' at position 2"));
+    }
+}
@@ -0,0 +1,296 @@
+//! Comparison of token streams.
+//!
+//! Two sources are considered token-equivalent when they scan to the same
+//! sequence of tokens, regardless of whitespace, comments or positions.
+//! This is useful when reviewing changes that should only affect formatting.
+//! When streams do differ, a minimal edit script describes what changed.
+
+use super::scanner::{ScanError, Scanner, Token, TokenPosition};
+
+/// The first point at which two token streams diverge
+///
+/// Either side is `None` when that stream ended before the other one
+#[derive(Debug, PartialEq)]
+pub struct Diff<'a> {
+    pub index: usize,
+    pub left: Option<&'a (TokenPosition, Token)>,
+    pub right: Option<&'a (TokenPosition, Token)>,
+}
+
+/// Compares two token streams while ignoring token positions
+///
+/// Returns `None` if the streams are token-equivalent, otherwise the first differing token of each stream
+pub fn token_diff<'a>(
+    a: &'a [(TokenPosition, Token)],
+    b: &'a [(TokenPosition, Token)],
+) -> Option<Diff<'a>> {
+    let index = a
+        .iter()
+        .zip(b.iter())
+        .position(|((_, x), (_, y))| x != y)
+        .unwrap_or_else(|| a.len().min(b.len()));
+
+    if index == a.len() && index == b.len() {
+        return None;
+    }
+
+    Some(Diff {
+        index,
+        left: a.get(index),
+        right: b.get(index),
+    })
+}
+
+/// A single step of an edit script turning one token stream into another
+///
+/// Indices refer to positions in the old token stream.
+#[derive(Debug, PartialEq)]
+pub enum Edit {
+    /// Insert a token before the old token at `at`
+    Insert { at: usize, token: Token },
+    /// Delete the old token at `at`
+    Delete { at: usize },
+    /// Replace the old token at `at`
+    Replace { at: usize, token: Token },
+}
+
+/// Operations on the shortest edit path, indexing into the old or new stream
+enum Step {
+    Keep,
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Finds the shortest edit path between two token streams using the Myers diff algorithm
+fn shortest_edit(old: &[Token], new: &[Token]) -> Vec<Step> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    let index = |k: isize| (k + max) as usize;
+
+    // Furthest reaching x for each diagonal k, recorded before every round for backtracking
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut steps = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            steps.push(Step::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                steps.push(Step::Insert((y - 1) as usize));
+            } else {
+                steps.push(Step::Delete((x - 1) as usize));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    steps.reverse();
+    steps
+}
+
+/// Computes a minimal edit script turning the old token stream into the new one
+///
+/// Tokens are compared by kind and value. A deletion directly followed by an insertion
+/// at the same place is reported as a single replacement.
+pub fn token_edit_script(old: &[Token], new: &[Token]) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::new();
+    let mut at = 0;
+
+    for step in shortest_edit(old, new) {
+        match step {
+            Step::Keep => at += 1,
+            Step::Delete(i) => {
+                edits.push(Edit::Delete { at: i });
+                at = i + 1;
+            }
+            Step::Insert(j) => {
+                let token = new[j].clone();
+                match edits.last() {
+                    Some(&Edit::Delete { at: deleted }) if deleted + 1 == at => {
+                        edits.pop();
+                        edits.push(Edit::Replace { at: deleted, token });
+                    }
+                    _ => edits.push(Edit::Insert { at, token }),
+                }
+            }
+        }
+    }
+
+    edits
+}
+
+/// Every token and error of a scan in source order, without positions
+pub type Scanned = Vec<Result<Token, ScanError>>;
+
+/// Scans an input, keeping tokens and errors interleaved as they were found
+fn scanned(scanner: &Scanner, input: &str) -> Scanned {
+    let mut scanned = Vec::new();
+    scanner.tokenize_cb(&mut input.as_bytes(), |result| {
+        scanned.push(result.map(|(_, token)| token).map_err(|(_, err)| err))
+    });
+    scanned
+}
+
+/// Runs two scanners over the same inputs and reports every input they tokenize differently
+///
+/// Each divergence holds the input and what the first and second scanner produced.
+/// Errors are kept in place among the tokens, so inputs which fail to scan are compared as well.
+/// This shows which existing sources a change to the grammar would affect.
+pub fn diff_tokenizations(inputs: &[&str], scanner_a: &Scanner, scanner_b: &Scanner) -> Vec<(String, Scanned, Scanned)> {
+    inputs
+        .iter()
+        .filter_map(|input| {
+            let a = scanned(scanner_a, input);
+            let b = scanned(scanner_b, input);
+            if a == b {
+                None
+            } else {
+                Some((input.to_string(), a, b))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_equivalent() {
+        let a = tokenize(&mut "f = \\x.x+1;".as_bytes()).unwrap();
+        let b = tokenize(&mut "f   =   \\x . x + 1 ; # increment".as_bytes()).unwrap();
+        assert_eq!(token_diff(&a, &b), None);
+    }
+
+    #[test]
+    fn test_changed_literal() {
+        let a = tokenize(&mut "f = \\x.x+1;".as_bytes()).unwrap();
+        let b = tokenize(&mut "f = \\x.\n  x + 2;".as_bytes()).unwrap();
+        let diff = token_diff(&a, &b).unwrap();
+        assert_eq!(diff.index, 7);
+        assert_eq!(diff.left, Some(&a[7]));
+        assert_eq!(diff.right, Some(&b[7]));
+        assert_eq!(diff.left.unwrap().1, Token::Int(1));
+        assert_eq!(diff.right.unwrap().1, Token::Int(2));
+    }
+
+    #[test]
+    fn test_different_length() {
+        let a = tokenize(&mut "x + 1".as_bytes()).unwrap();
+        let b = tokenize(&mut "x".as_bytes()).unwrap();
+        let diff = token_diff(&a, &b).unwrap();
+        assert_eq!(diff.index, 1);
+        assert_eq!(diff.left.unwrap().1, Token::Add);
+        assert_eq!(diff.right, None);
+    }
+
+    fn scan(source: &str) -> Vec<Token> {
+        tokenize(&mut source.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_script_replace() {
+        assert_eq!(
+            token_edit_script(&scan("1 + 2"), &scan("1 + 3")),
+            vec![Edit::Replace { at: 2, token: Token::Int(3) }]
+        );
+    }
+
+    #[test]
+    fn test_edit_script_insert() {
+        assert_eq!(
+            token_edit_script(&scan("1 + 2"), &scan("1 + 2 + 3")),
+            vec![
+                Edit::Insert { at: 3, token: Token::Add },
+                Edit::Insert { at: 3, token: Token::Int(3) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_edit_script_delete() {
+        assert_eq!(
+            token_edit_script(&scan("f(x, y)"), &scan("f(y)")),
+            vec![Edit::Delete { at: 1 }, Edit::Delete { at: 2 }]
+        );
+        assert_eq!(token_edit_script(&scan("a b"), &scan("a b")), vec![]);
+        assert_eq!(
+            token_edit_script(&[], &scan("a")),
+            vec![Edit::Insert { at: 0, token: Token::Name("a".to_string()) }]
+        );
+    }
+
+    #[test]
+    fn test_diff_tokenizations() {
+        use crate::lang::scanner::{ScannerConfig, TokenKind};
+
+        let without_pipe = Scanner::from_config(ScannerConfig {
+            disabled_rules: vec![TokenKind::Pipe],
+            ..ScannerConfig::default()
+        });
+        let inputs = ["a | b", "x |> f", "a || b", "a | $", "x |> f $"];
+        let divergences = diff_tokenizations(&inputs, &without_pipe, &Scanner::default());
+
+        let x = Ok(Token::Name("x".to_string()));
+        let f = Ok(Token::Name("f".to_string()));
+        let unknown = Err(ScanError::UnknownToken("$".to_string()));
+        assert_eq!(
+            divergences,
+            vec![
+                (
+                    "x |> f".to_string(),
+                    vec![x.clone(), Ok(Token::Bor), Ok(Token::Greater), f.clone()],
+                    vec![x.clone(), Ok(Token::Pipe), f.clone()],
+                ),
+                (
+                    "x |> f $".to_string(),
+                    vec![x.clone(), Ok(Token::Bor), Ok(Token::Greater), f.clone(), unknown.clone()],
+                    vec![x, Ok(Token::Pipe), f, unknown],
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Rendering of token streams into interchange formats.
+//!
+//! These formats are meant for inspecting tokens in tools outside of the
+//! Rust ecosystem, such as spreadsheets.
+
+use super::scanner::{Token, TokenPosition};
+use std::io::{self, Write};
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Payload of a token as text, empty for tokens without one
+fn payload(token: &Token) -> String {
+    match token {
+        Token::Int(i) | Token::Char(i) => i.to_string(),
+        Token::Float(f) => f.to_string(),
+        Token::Name(s) | Token::Call(s) | Token::Builtin(s) | Token::Shebang(s) | Token::LineComment(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Writes tokens as CSV with a `line,col,kind,value` header
+///
+/// Columns are byte positions within the line, and chars are written as their code point.
+pub fn tokens_to_csv<W: Write>(tokens: &[(TokenPosition, Token)], out: &mut W) -> io::Result<()> {
+    writeln!(out, "line,col,kind,value")?;
+    for (pos, token) in tokens {
+        writeln!(
+            out,
+            "{},{},{:?},{}",
+            pos.line,
+            pos.position,
+            token.kind(),
+            csv_field(&payload(token))
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::{tokenize_with_config, ScannerConfig};
+
+    #[test]
+    fn test_tokens_to_csv() {
+        let config = ScannerConfig {
+            keep_comments: true,
+            ..ScannerConfig::default()
+        };
+        let tokens = tokenize_with_config(&mut "f(1.5) # a, \"b\"".as_bytes(), &config).unwrap();
+
+        let mut out = Vec::new();
+        tokens_to_csv(&tokens, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "line,col,kind,value\n\
+             0,0,Call,f\n\
+             0,2,Float,1.5\n\
+             0,5,Rpar,\n\
+             0,7,LineComment,\" a, \"\"b\"\"\"\n"
+        );
+    }
+}
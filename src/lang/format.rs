@@ -0,0 +1,132 @@
+//! Canonical formatting of source text.
+//!
+//! Formatting works on the token stream, so the output always scans to the
+//! same tokens as the input.
+
+use super::scanner::{tokenize_with_config, ScanErrors, ScannerConfig, Token, TokenPosition};
+
+/// Whether a token is written without a space after it
+fn binds_right(token: &Token) -> bool {
+    matches!(token, Token::Lpar | Token::Call(_) | Token::Builtin(_) | Token::Lambda)
+}
+
+/// Whether a token is written without a space before it
+fn binds_left(token: &Token) -> bool {
+    matches!(token, Token::Rpar | Token::Comma | Token::Semicolon | Token::Period)
+}
+
+/// Re-emits the tokens of one line with canonical spacing
+fn format_line(tokens: &[(TokenPosition, Token)]) -> String {
+    let mut line = String::new();
+    let mut previous: Option<&Token> = None;
+
+    for (_, token) in tokens {
+        match previous {
+            Some(previous) if !binds_right(previous) && !binds_left(token) => line.push(' '),
+            _ => (),
+        }
+        line.push_str(&token.to_string());
+        previous = Some(token);
+    }
+
+    line.trim_end().to_string()
+}
+
+/// Rewrites a source with canonical whitespace
+///
+/// Tokens on a line are separated by a single space, except inside parentheses, after a lambda
+/// and before `,`, `;` and `.`. Indentation and trailing whitespace are removed, runs of blank lines
+/// are collapsed into one and the output ends with a newline. Comments are kept.
+pub fn normalize_whitespace(source: &str) -> Result<String, ScanErrors> {
+    let config = ScannerConfig {
+        keep_shebang: true,
+        keep_comments: true,
+        ..ScannerConfig::default()
+    };
+    let tokens = tokenize_with_config(&mut source.as_bytes(), &config)?;
+
+    let mut output = String::new();
+    let mut next_line = 0;
+    let mut start = 0;
+
+    while start < tokens.len() {
+        let line = tokens[start].0.line;
+        let end = tokens[start..]
+            .iter()
+            .position(|(pos, _)| pos.line != line)
+            .map_or(tokens.len(), |n| start + n);
+
+        if line > next_line && !output.is_empty() {
+            output.push('\n');
+        }
+        output.push_str(&format_line(&tokens[start..end]));
+        output.push('\n');
+
+        next_line = line + 1;
+        start = end;
+    }
+
+    Ok(output)
+}
+
+/// Computes the column at which the `=` of each definition should be placed to line up with its neighbours
+///
+/// A definition is a line starting with a name followed by `=`, and consecutive definition lines form a group.
+/// One column is returned per definition, in source order. It is the column just past the longest name
+/// of the group plus one space.
+pub fn alignment_columns(tokens: &[(TokenPosition, Token)]) -> Vec<usize> {
+    // Line and column of the `=` after the name of every definition
+    let mut definitions: Vec<(usize, usize)> = Vec::new();
+    for (i, (pos, token)) in tokens.iter().enumerate() {
+        let starts_line = i == 0 || tokens[i - 1].0.line != pos.line;
+        if let (true, Token::Name(_), Some((_, Token::Assign))) = (starts_line, token, tokens.get(i + 1)) {
+            definitions.push((pos.line, pos.end().position + 1));
+        }
+    }
+
+    let mut columns = Vec::with_capacity(definitions.len());
+    let mut start = 0;
+    while start < definitions.len() {
+        let mut end = start + 1;
+        while end < definitions.len() && definitions[end].0 == definitions[end - 1].0 + 1 {
+            end += 1;
+        }
+
+        let column = definitions[start..end].iter().map(|(_, column)| *column).max().unwrap();
+        columns.extend(std::iter::repeat_n(column, end - start));
+        start = end;
+    }
+
+    columns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_normalize_whitespace() {
+        let source = "f=\\x .x*2   # double   \n\n\n   y = f( 1,2 )+.5;\t\n";
+        let formatted = normalize_whitespace(source).unwrap();
+        assert_eq!(formatted, "f = \\x. x * 2 # double\n\ny = f(1, 2) + 0.5;\n");
+
+        let tokens = |s: &str| -> Vec<Token> {
+            tokenize(&mut s.as_bytes()).unwrap().into_iter().map(|(_, t)| t).collect()
+        };
+        assert_eq!(tokens(&formatted), tokens(source));
+        assert_eq!(normalize_whitespace(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_normalize_whitespace_errors() {
+        assert!(normalize_whitespace("x = $").is_err());
+        assert_eq!(normalize_whitespace("").unwrap(), "");
+    }
+
+    #[test]
+    fn test_alignment_columns() {
+        let tokens = tokenize(&mut "a = 1\nlong_name = 2\nmid = a\n\nx = 4\nf(y = 1)".as_bytes()).unwrap();
+        assert_eq!(alignment_columns(&tokens), vec![10, 10, 10, 2]);
+    }
+}
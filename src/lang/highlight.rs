@@ -0,0 +1,283 @@
+//! Classification of tokens for syntax highlighting.
+//!
+//! Editors consume highlighting information in a handful of established formats.
+//! This module maps scanned tokens onto those formats.
+
+use super::scanner::{tokenize_cb, Token, TokenPosition};
+use super::source::LineIndex;
+use std::ops::Range;
+
+/// Coarse classification of source text for editor themes
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Category {
+    Literal,
+    Identifier,
+    Operator,
+    Delimiter,
+    /// Reserved words, the language does not have any yet
+    Keyword,
+    Comment,
+    /// Text which the scanner could not tokenize
+    Error,
+}
+
+impl Token {
+    /// Gets the coarse category of the token
+    pub fn category(&self) -> Category {
+        use Token::*;
+        match self {
+            Int(_) | Float(_) | Char(_) => Category::Literal,
+            Name(_) | Call(_) | Builtin(_) => Category::Identifier,
+            Shr | Shl | Equal | Neq | Leq | Geq | Less | Greater | Add | Sub | Mul | Div | Assign | Not | And | Or
+            | Bnot | Band | Bor | Xor | Pipe | Lambda => Category::Operator,
+            Comma | Period | Semicolon | Lpar | Rpar => Category::Delimiter,
+            Shebang(_) | LineComment(_) => Category::Comment,
+        }
+    }
+}
+
+/// Textmate scope name of a token
+fn scope_name(token: &Token) -> &'static str {
+    use Token::*;
+    match token {
+        Int(_) | Float(_) => "constant.numeric",
+        Char(_) => "constant.character",
+        Shr | Shl | Equal | Neq | Leq | Geq | Less | Greater | Add | Sub | Mul | Div | Assign | Not | And | Or
+        | Bnot | Band | Bor | Xor | Pipe => "keyword.operator",
+        Lambda => "storage.type.function",
+        Comma | Period | Semicolon => "punctuation.separator",
+        Lpar | Rpar => "punctuation.section.parens",
+        Call(_) => "entity.name.function",
+        Builtin(_) => "support.function",
+        Name(_) => "variable.other",
+        Shebang(_) => "comment.line.shebang",
+        LineComment(_) => "comment.line.number-sign",
+    }
+}
+
+/// Maps each token to its Textmate scope name
+///
+/// The byte ranges are absolute offsets into `source`, the text the tokens were scanned from.
+/// Tokens positioned outside of the source are left out.
+pub fn capture_names(source: &str, tokens: &[(TokenPosition, Token)]) -> Vec<(Range<usize>, &'static str)> {
+    let index = LineIndex::new(source);
+    tokens
+        .iter()
+        .filter_map(|(pos, token)| {
+            let start = index.byte_offset(pos)?;
+            let end = index.byte_offset(&pos.end())?;
+            Some((start..end, scope_name(token)))
+        })
+        .collect()
+}
+
+/// Semantic token types understood by LSP clients, in legend order
+static SEMANTIC_TOKEN_TYPES: [&str; 7] = ["number", "string", "operator", "keyword", "function", "variable", "comment"];
+
+/// Returns the token type legend to register with an LSP client
+///
+/// The indices produced by `semantic_tokens` refer into this list.
+pub fn semantic_token_legend() -> Vec<&'static str> {
+    SEMANTIC_TOKEN_TYPES.to_vec()
+}
+
+/// LSP legend index of a token, `None` for punctuation which LSP has no type for
+fn semantic_token_type(token: &Token) -> Option<u32> {
+    use Token::*;
+    let name = match token {
+        Int(_) | Float(_) => "number",
+        Char(_) => "string",
+        Lambda => "keyword",
+        Call(_) | Builtin(_) => "function",
+        Name(_) => "variable",
+        Shebang(_) | LineComment(_) => "comment",
+        Comma | Period | Semicolon | Lpar | Rpar => return None,
+        _ => "operator",
+    };
+    SEMANTIC_TOKEN_TYPES.iter().position(|t| *t == name).map(|i| i as u32)
+}
+
+/// Encodes tokens as LSP semantic tokens
+///
+/// Every token becomes five integers: line and start relative to the previous token,
+/// length, token type and modifiers. Punctuation is left out.
+/// LSP counts columns in UTF-16 code units. Every token except comments is ASCII and
+/// comments run to the end of the line, so only comment lengths need converting.
+/// The encoding is relative, so tokens are sorted into source order first.
+pub fn semantic_tokens(tokens: &[(TokenPosition, Token)]) -> Vec<u32> {
+    let mut data = Vec::new();
+    let (mut prev_line, mut prev_start) = (0, 0);
+
+    let mut sorted: Vec<&(TokenPosition, Token)> = tokens.iter().collect();
+    sorted.sort_by_key(|(pos, _)| (pos.line, pos.position));
+
+    for (pos, token) in sorted {
+        let token_type = match semantic_token_type(token) {
+            Some(token_type) => token_type,
+            None => continue,
+        };
+        let length = match token {
+            Token::Shebang(s) => 2 + s.encode_utf16().count(),
+            Token::LineComment(s) => 1 + s.encode_utf16().count(),
+            _ => pos.length,
+        };
+
+        let delta_line = pos.line - prev_line;
+        let delta_start = if delta_line == 0 { pos.position - prev_start } else { pos.position };
+        data.extend(&[delta_line as u32, delta_start as u32, length as u32, token_type, 0]);
+
+        prev_line = pos.line;
+        prev_start = pos.position;
+    }
+
+    data
+}
+
+/// Escapes text for use in HTML
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Renders the text between two tokens, where everything from a `#` onwards is a comment
+fn html_gap(gap: &str) -> String {
+    match gap.find('#') {
+        Some(i) => format!(
+            "{}<span class=\"comment\">{}</span>",
+            html_escape(&gap[..i]),
+            html_escape(&gap[i..])
+        ),
+        None => html_escape(gap),
+    }
+}
+
+/// Renders a source as an HTML `<pre>` block with every token wrapped in a span
+///
+/// Span classes are the lowercase names of the token categories, and text which fails to scan gets the class `error`.
+/// Whitespace and comments are kept as they are, so the fragment reads exactly like the source.
+pub fn to_html(source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut spans: Vec<Vec<(TokenPosition, &'static str)>> = vec![Vec::new(); lines.len()];
+
+    tokenize_cb(&mut source.as_bytes(), |result| {
+        let (pos, class) = match result {
+            Ok((pos, token)) => {
+                let class = match token.category() {
+                    Category::Literal => "literal",
+                    Category::Identifier => "identifier",
+                    Category::Operator => "operator",
+                    Category::Delimiter => "delimiter",
+                    Category::Keyword => "keyword",
+                    Category::Comment => "comment",
+                    Category::Error => "error",
+                };
+                (pos, class)
+            }
+            Err((pos, _)) => (pos, "error"),
+        };
+        if let Some(line) = spans.get_mut(pos.line) {
+            line.push((pos, class));
+        }
+    });
+
+    let mut html = String::from("<pre class=\"flang\">");
+    for (line_num, line) in lines.iter().enumerate() {
+        if line_num > 0 {
+            html.push('\n');
+        }
+        let mut offset = 0;
+        for (pos, class) in &spans[line_num] {
+            let end = pos.end().position;
+            html.push_str(&html_gap(&line[offset..pos.position]));
+            html.push_str(&format!("<span class=\"{}\">{}</span>", class, html_escape(&line[pos.position..end])));
+            offset = end;
+        }
+        html.push_str(&html_gap(&line[offset..]));
+    }
+    if source.ends_with('\n') {
+        html.push('\n');
+    }
+    html.push_str("</pre>");
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_capture_names() {
+        let source = "a = \\x.f(x)+1;";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(
+            capture_names(source, &tokens),
+            vec![
+                (0..1, "variable.other"),
+                (2..3, "keyword.operator"),
+                (4..5, "storage.type.function"),
+                (5..6, "variable.other"),
+                (6..7, "punctuation.separator"),
+                (7..9, "entity.name.function"),
+                (9..10, "variable.other"),
+                (10..11, "punctuation.section.parens"),
+                (11..12, "keyword.operator"),
+                (12..13, "constant.numeric"),
+                (13..14, "punctuation.separator"),
+            ]
+        );
+
+        let source = "a\n  b";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(capture_names(source, &tokens), vec![(0..1, "variable.other"), (4..5, "variable.other")]);
+    }
+
+    #[test]
+    fn test_category() {
+        assert_eq!(Token::Int(1).category(), Category::Literal);
+        assert_eq!(Token::Name("x".to_string()).category(), Category::Identifier);
+        assert_eq!(Token::Call("f".to_string()).category(), Category::Identifier);
+        assert_eq!(Token::Add.category(), Category::Operator);
+        assert_eq!(Token::Lpar.category(), Category::Delimiter);
+        assert_eq!(Token::Shebang("/bin/flang".to_string()).category(), Category::Comment);
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let tokens = tokenize(&mut "f = \\x.\n  g(x) + 1.5".as_bytes()).unwrap();
+        assert_eq!(
+            semantic_tokens(&tokens),
+            vec![
+                0, 0, 1, 5, 0, // f
+                0, 2, 1, 2, 0, // =
+                0, 2, 1, 3, 0, // \
+                0, 1, 1, 5, 0, // x
+                1, 2, 2, 4, 0, // g(
+                0, 2, 1, 5, 0, // x
+                0, 3, 1, 2, 0, // +
+                0, 2, 3, 0, 0, // 1.5
+            ]
+        );
+        assert_eq!(semantic_token_legend()[5], "variable");
+
+        let mut reversed = tokens.clone();
+        reversed.reverse();
+        assert_eq!(semantic_tokens(&reversed), semantic_tokens(&tokens));
+    }
+
+    #[test]
+    fn test_to_html() {
+        assert_eq!(
+            to_html("a<b && c # x > 0\n  $"),
+            "<pre class=\"flang\"><span class=\"identifier\">a</span><span class=\"operator\">&lt;</span>\
+             <span class=\"identifier\">b</span> <span class=\"operator\">&amp;&amp;</span> \
+             <span class=\"identifier\">c</span> <span class=\"comment\"># x &gt; 0</span>\n  \
+             <span class=\"error\">$</span></pre>"
+        );
+        assert_eq!(
+            to_html("f(1)\n"),
+            "<pre class=\"flang\"><span class=\"identifier\">f(</span><span class=\"literal\">1</span>\
+             <span class=\"delimiter\">)</span>\n</pre>"
+        );
+    }
+}
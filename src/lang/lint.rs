@@ -0,0 +1,228 @@
+//! Optional style checks over source text and token streams.
+//!
+//! Lints never fail a scan, they only produce warnings
+//! consisting of a position and a message.
+
+use super::operators::{is_binary_operator, is_unary_position};
+use super::scanner::{Token, TokenPosition};
+use super::source::LineIndex;
+
+/// Reports every hard tab in the source
+pub fn check_tabs(source: &str) -> Vec<(TokenPosition, String)> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(line, text)| {
+            text.match_indices('\t').map(move |(position, _)| {
+                (
+                    TokenPosition {
+                        line,
+                        position,
+                        length: 1,
+                    },
+                    "hard tab".to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Reports binary operators which are not surrounded by whitespace
+///
+/// A `-` in unary position, such as `-5`, is exempt.
+/// Comment tokens are skipped, so the result does not depend on `keep_comments`.
+pub fn check_operator_spacing(source: &str, tokens: &[(TokenPosition, Token)]) -> Vec<(TokenPosition, String)> {
+    let mut warnings = Vec::new();
+    let index = LineIndex::new(source);
+    let code: Vec<&(TokenPosition, Token)> = tokens
+        .iter()
+        .filter(|(_, token)| !matches!(token, Token::Shebang(_) | Token::LineComment(_)))
+        .collect();
+
+    for (i, (pos, token)) in code.iter().copied().enumerate() {
+        if !is_binary_operator(token.kind()) {
+            continue;
+        }
+
+        let previous = if i > 0 { code.get(i - 1).copied() } else { None };
+        if *token == Token::Sub && is_unary_position(previous.map(|(_, t)| t.kind())) {
+            continue;
+        }
+
+        let before = previous.map_or(" ", |(p, _)| index.gap_between(&p.end(), pos));
+        let after = code.get(i + 1).map_or(" ", |(p, _)| index.gap_between(&pos.end(), p));
+
+        if before.is_empty() || after.is_empty() {
+            warnings.push((pos.clone(), format!("missing space around '{}'", token)));
+        }
+    }
+
+    warnings
+}
+
+/// Whether a token is a literal value
+fn is_literal(token: &Token) -> bool {
+    matches!(token, Token::Int(_) | Token::Float(_) | Token::Char(_))
+}
+
+/// Reports literals which directly follow another literal, such as `1 2`
+///
+/// Function application always uses parentheses, as in `f(1)`, so two adjacent literals
+/// can never be an application and almost always indicate a missing operator.
+/// The warning is placed on the second literal.
+pub fn check_adjacent_literals(tokens: &[(TokenPosition, Token)]) -> Vec<(TokenPosition, String)> {
+    tokens
+        .windows(2)
+        .filter(|pair| is_literal(&pair[0].1) && is_literal(&pair[1].1))
+        .map(|pair| {
+            (
+                pair[1].0.clone(),
+                format!("missing operator between '{}' and '{}'?", pair[0].1, pair[1].1),
+            )
+        })
+        .collect()
+}
+
+/// House style for the space between a function and its argument list
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CallStyle {
+    /// Calls are written `f(x)`
+    Unspaced,
+    /// Calls are written `f (x)`
+    Spaced,
+}
+
+/// Reports calls which do not follow the chosen call style
+///
+/// A name followed by a parenthesis on the same line is taken to be a spaced call.
+/// Builtins can only be written unspaced and are never reported.
+pub fn check_call_spacing(source: &str, tokens: &[(TokenPosition, Token)], style: CallStyle) -> Vec<(TokenPosition, String)> {
+    let mut warnings = Vec::new();
+    let index = LineIndex::new(source);
+
+    for (i, (pos, token)) in tokens.iter().enumerate() {
+        match (token, style) {
+            (Token::Call(name), CallStyle::Spaced) => {
+                warnings.push((pos.clone(), format!("missing space before the arguments of '{}'", name)));
+            }
+            (Token::Name(name), CallStyle::Unspaced) => {
+                if let Some((next, Token::Lpar)) = tokens.get(i + 1) {
+                    let gap = index.gap_between(&pos.end(), next);
+                    if !gap.is_empty() && !gap.contains('\n') {
+                        warnings.push((pos.clone(), format!("space before the arguments of '{}'", name)));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::{tokenize, tokenize_with_config, ScannerConfig};
+
+    fn spacing(source: &str) -> Vec<(TokenPosition, String)> {
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        check_operator_spacing(source, &tokens)
+    }
+
+    #[test]
+    fn test_check_tabs() {
+        let warnings = check_tabs("f = \\x.\n\tx +\t1;");
+        assert_eq!(
+            warnings,
+            vec![
+                (TokenPosition { line: 1, position: 0, length: 1 }, "hard tab".to_string()),
+                (TokenPosition { line: 1, position: 4, length: 1 }, "hard tab".to_string()),
+            ]
+        );
+        assert!(check_tabs("f = \\x.\n  x + 1;").is_empty());
+    }
+
+    #[test]
+    fn test_operator_spacing() {
+        assert_eq!(
+            spacing("1+2"),
+            vec![(TokenPosition { line: 0, position: 1, length: 1 }, "missing space around '+'".to_string())]
+        );
+        assert_eq!(spacing("a <=b").len(), 1);
+        assert!(spacing("1 + 2").is_empty());
+        assert!(spacing("x = 1 +\n  2").is_empty());
+    }
+
+    #[test]
+    fn test_operator_spacing_unary() {
+        assert!(spacing("-5").is_empty());
+        assert!(spacing("x = -5 * (-y)").is_empty());
+        assert!(spacing("f(-1, !x)").is_empty());
+        assert_eq!(spacing("x-5").len(), 1);
+        assert_eq!(spacing("x = a\n  -b").len(), 1);
+    }
+
+    #[test]
+    fn test_operator_spacing_comments() {
+        let source = "x = a # c\n  -b";
+        let config = ScannerConfig {
+            keep_comments: true,
+            ..ScannerConfig::default()
+        };
+        let tokens = tokenize_with_config(&mut source.as_bytes(), &config).unwrap();
+        assert_eq!(check_operator_spacing(source, &tokens), spacing(source));
+        assert_eq!(spacing(source).len(), 1);
+    }
+
+    #[test]
+    fn test_operator_spacing_pipe() {
+        assert_eq!(
+            spacing("x|>f"),
+            vec![(TokenPosition { line: 0, position: 1, length: 2 }, "missing space around '|>'".to_string())]
+        );
+        assert!(spacing("x |> f").is_empty());
+    }
+
+    #[test]
+    fn test_adjacent_literals() {
+        let tokens = tokenize(&mut "1 2".as_bytes()).unwrap();
+        assert_eq!(
+            check_adjacent_literals(&tokens),
+            vec![(
+                TokenPosition { line: 0, position: 2, length: 1 },
+                "missing operator between '1' and '2'?".to_string()
+            )]
+        );
+
+        let tokens = tokenize(&mut "f(1, 'a') + 2;\n3.5".as_bytes()).unwrap();
+        assert!(check_adjacent_literals(&tokens).is_empty());
+
+        let tokens = tokenize(&mut "1 + 2".as_bytes()).unwrap();
+        assert!(check_adjacent_literals(&tokens).is_empty());
+    }
+
+    #[test]
+    fn test_call_spacing() {
+        let source = "y = f (x) + g(x) + _put(x)";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(
+            check_call_spacing(source, &tokens, CallStyle::Unspaced),
+            vec![(
+                TokenPosition { line: 0, position: 4, length: 1 },
+                "space before the arguments of 'f'".to_string()
+            )]
+        );
+        assert_eq!(
+            check_call_spacing(source, &tokens, CallStyle::Spaced),
+            vec![(
+                TokenPosition { line: 0, position: 12, length: 2 },
+                "missing space before the arguments of 'g'".to_string()
+            )]
+        );
+
+        let source = "y = f(x) + a\n  (b)";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert!(check_call_spacing(source, &tokens, CallStyle::Unspaced).is_empty());
+    }
+}
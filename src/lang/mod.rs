@@ -1 +1,13 @@
+pub mod analysis;
+pub mod diagnostics;
+pub mod diff;
+pub mod export;
+pub mod format;
+pub mod highlight;
+pub mod lint;
+pub mod operators;
+pub mod preprocess;
 pub mod scanner;
+pub mod shrink;
+pub mod source;
+pub mod stream;
@@ -0,0 +1,100 @@
+//! Precedence and associativity of the binary operators.
+//!
+//! This table is the single source of truth for how operators bind,
+//! and is meant to be read by the parser as well as by documentation and tooling.
+
+use super::scanner::TokenKind;
+
+/// Associativity of a binary operator
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+/// Binary operators with their precedence and associativity
+///
+/// A higher precedence binds tighter
+static OPERATORS: [(TokenKind, u8, Assoc); 18] = [
+    (TokenKind::Assign, 0, Assoc::Right),
+    (TokenKind::Or, 1, Assoc::Left),
+    (TokenKind::And, 2, Assoc::Left),
+    (TokenKind::Bor, 3, Assoc::Left),
+    (TokenKind::Xor, 4, Assoc::Left),
+    (TokenKind::Band, 5, Assoc::Left),
+    (TokenKind::Equal, 6, Assoc::Left),
+    (TokenKind::Neq, 6, Assoc::Left),
+    (TokenKind::Less, 7, Assoc::Left),
+    (TokenKind::Greater, 7, Assoc::Left),
+    (TokenKind::Leq, 7, Assoc::Left),
+    (TokenKind::Geq, 7, Assoc::Left),
+    (TokenKind::Shl, 8, Assoc::Left),
+    (TokenKind::Shr, 8, Assoc::Left),
+    (TokenKind::Add, 9, Assoc::Left),
+    (TokenKind::Sub, 9, Assoc::Left),
+    (TokenKind::Mul, 10, Assoc::Left),
+    (TokenKind::Div, 10, Assoc::Left),
+];
+
+/// Returns the precedence and associativity of every binary operator
+pub fn operator_table() -> &'static [(TokenKind, u8, Assoc)] {
+    &OPERATORS
+}
+
+/// Looks up the precedence and associativity of a single operator
+///
+/// Returns `None` if the token kind is not a binary operator
+pub fn operator(kind: TokenKind) -> Option<(u8, Assoc)> {
+    OPERATORS
+        .iter()
+        .find(|(k, _, _)| *k == kind)
+        .map(|&(_, prec, assoc)| (prec, assoc))
+}
+
+/// Whether a token kind is written between two operands
+///
+/// Besides the operators in the precedence table this includes the pipeline `|>`,
+/// which the parser desugars into a call rather than treating as an operator.
+pub fn is_binary_operator(kind: TokenKind) -> bool {
+    kind == TokenKind::Pipe || operator(kind).is_some()
+}
+
+/// Whether a `-` following a token of the given kind is a unary minus
+///
+/// `None` stands for the start of the input, or a token which could not be scanned.
+/// Comments are skipped by callers, so `previous` is always the last code token.
+pub fn is_unary_position(previous: Option<TokenKind>) -> bool {
+    !matches!(
+        previous,
+        Some(TokenKind::Int) | Some(TokenKind::Float) | Some(TokenKind::Char) | Some(TokenKind::Name) | Some(TokenKind::Rpar)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_associativity() {
+        assert_eq!(operator(TokenKind::Add).unwrap().1, Assoc::Left);
+        assert_eq!(operator(TokenKind::Assign).unwrap().1, Assoc::Right);
+        assert!(operator_table().contains(&(TokenKind::Add, 9, Assoc::Left)));
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert!(operator(TokenKind::Mul).unwrap().0 > operator(TokenKind::Add).unwrap().0);
+        assert!(operator(TokenKind::And).unwrap().0 > operator(TokenKind::Or).unwrap().0);
+        assert_eq!(operator(TokenKind::Lambda), None);
+    }
+
+    #[test]
+    fn test_binary_operators() {
+        assert!(is_binary_operator(TokenKind::Add));
+        assert!(is_binary_operator(TokenKind::Pipe));
+        assert!(!is_binary_operator(TokenKind::Not));
+        assert!(is_unary_position(None));
+        assert!(is_unary_position(Some(TokenKind::Lpar)));
+        assert!(!is_unary_position(Some(TokenKind::Rpar)));
+    }
+}
@@ -0,0 +1,161 @@
+//! Lightweight textual macros.
+//!
+//! A line of the form `#define NAME tokens...` defines a macro which is expanded
+//! wherever `NAME` is used as a name. Since `#` starts a comment, define lines are
+//! ignored by the plain scanner, so sources using defines still tokenize without them.
+
+use super::scanner::{tokenize, tokenize_region, ScanError, ScanErrors, ScanResult, Token, TokenPosition};
+use std::collections::HashMap;
+
+/// Parses a single define line into its name and the tokens of its body
+///
+/// Returns `None` if the line is not a define
+fn parse_define(line_num: usize, line: &str) -> Option<Result<(String, Vec<Token>), ScanErrors>> {
+    let directive = line.trim_start().strip_prefix("#define")?;
+    if !directive.is_empty() && !directive.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let directive = directive.trim_start();
+    let name = directive.split_whitespace().next().unwrap_or("");
+    let body = directive[name.len()..].trim_start();
+
+    let position = TokenPosition {
+        line: line_num,
+        position: line.len() - directive.len(),
+        length: name.len(),
+    };
+
+    match tokenize(&mut name.as_bytes()) {
+        Ok(ref tokens) if tokens.len() == 1 && tokens[0].1 == Token::Name(name.to_string()) => (),
+        _ => return Some(Err(vec![(position, ScanError::InvalidDefine(name.to_string()))])),
+    }
+
+    let base = TokenPosition {
+        line: line_num,
+        position: line.len() - body.len(),
+        length: 0,
+    };
+
+    Some(
+        tokenize_region(body, base)
+            .map(|tokens| (name.to_string(), tokens.into_iter().map(|(_, token)| token).collect())),
+    )
+}
+
+/// Appends the expansion of a define to `out`, expanding nested defines
+///
+/// `stack` holds the defines currently being expanded and is used to detect recursion
+fn expand(
+    name: &str,
+    defines: &HashMap<String, Vec<Token>>,
+    stack: &mut Vec<String>,
+    out: &mut Vec<Token>,
+) -> Result<(), ScanError> {
+    if stack.iter().any(|s| s == name) {
+        return Err(ScanError::RecursiveDefine(name.to_string()));
+    }
+
+    stack.push(name.to_string());
+    for token in &defines[name] {
+        match token {
+            Token::Name(s) if defines.contains_key(s) => expand(s, defines, stack, out)?,
+            _ => out.push(token.clone()),
+        }
+    }
+    stack.pop();
+
+    Ok(())
+}
+
+/// Tokenizes a source while expanding `#define` macros
+///
+/// All defines are collected before any expansion takes place.
+/// Every token produced by an expansion is given the position of the name it replaced.
+pub fn tokenize_with_defines(source: &str) -> ScanResult {
+    let mut defines: HashMap<String, Vec<Token>> = HashMap::new();
+    let mut errors: ScanErrors = Vec::new();
+
+    for (line_num, line) in source.lines().enumerate() {
+        match parse_define(line_num, line) {
+            Some(Ok((name, body))) => {
+                defines.insert(name, body);
+            }
+            Some(Err(errs)) => errors.extend(errs),
+            None => (),
+        }
+    }
+
+    let mut tokens: Vec<(TokenPosition, Token)> = Vec::new();
+
+    match tokenize(&mut source.as_bytes()) {
+        Ok(scanned) => {
+            for (pos, token) in scanned {
+                match token {
+                    Token::Name(ref name) if defines.contains_key(name) => {
+                        let mut expansion = Vec::new();
+                        match expand(name, &defines, &mut Vec::new(), &mut expansion) {
+                            Ok(()) => tokens.extend(expansion.into_iter().map(|t| (pos.clone(), t))),
+                            Err(err) => errors.push((pos, err)),
+                        }
+                    }
+                    _ => tokens.push((pos, token)),
+                }
+            }
+        }
+        Err(errs) => errors.extend(errs),
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|(pos, _)| (pos.line, pos.position));
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_define() {
+        let tokens = tokenize_with_defines("#define PI 3.14159\nPI * r").unwrap();
+        let expected = tokenize(&mut "3.14159 * r".as_bytes()).unwrap();
+
+        let kinds: Vec<&Token> = tokens.iter().map(|(_, t)| t).collect();
+        let expected: Vec<&Token> = expected.iter().map(|(_, t)| t).collect();
+        assert_eq!(kinds, expected);
+
+        // The expanded literal is reported at the use of PI
+        assert_eq!(tokens[0].0, TokenPosition { line: 1, position: 0, length: 2 });
+    }
+
+    #[test]
+    fn test_nested_define() {
+        let tokens = tokenize_with_defines("#define TAU 2 * PI\n#define PI 3.0\nTAU").unwrap();
+        let kinds: Vec<&Token> = tokens.iter().map(|(_, t)| t).collect();
+        assert_eq!(kinds, vec![&Token::Int(2), &Token::Mul, &Token::Float(3.0)]);
+    }
+
+    #[test]
+    fn test_recursive_define() {
+        let errs = tokenize_with_defines("#define A B + 1\n#define B A\nx = A").unwrap_err();
+        assert_eq!(
+            errs,
+            vec![(
+                TokenPosition { line: 2, position: 4, length: 1 },
+                ScanError::RecursiveDefine("A".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_invalid_define() {
+        let errs = tokenize_with_defines("#define 1 2").unwrap_err();
+        assert_eq!(errs[0].1, ScanError::InvalidDefine("1".to_string()));
+
+        // Plain comments are not defines
+        assert!(tokenize_with_defines("#defined later\n# define x").unwrap().is_empty());
+    }
+}
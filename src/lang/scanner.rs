@@ -1,18 +1,33 @@
+//! The Scanner is the first step of any non-trivial parsing task.
+//! The responsibility of the scanner is to take a stream of raw text and
+//! turn it into a list of tokens which can be used by later parts of an
+//! interpreter or compiler.
+
+use super::operators::{is_binary_operator, is_unary_position};
 use regex::{Match, Regex};
 use std::fmt;
 use std::io::BufRead;
+use std::str;
 
-/// The Scanner is the first step of any non-trivial parsing task.
-/// The responsibility of the scanner is to take a stream of raw text and
-/// turn it into a list of tokens which can be used by later parts of an
-/// interpreter or compiler.
-
-/// Line, and symbol-position for parsed tokens
+/// Line, symbol-position and length for parsed tokens
 /// This is useful for later printing of debug- and error information
 #[derive(PartialEq, Debug, Clone)]
 pub struct TokenPosition {
-    line: usize,
-    position: usize,
+    pub line: usize,
+    pub position: usize,
+    /// Length of the token in the source line, in bytes
+    pub length: usize,
+}
+
+impl TokenPosition {
+    /// The empty position directly following the token
+    pub fn end(&self) -> TokenPosition {
+        TokenPosition {
+            line: self.line,
+            position: self.position + self.length,
+            length: 0,
+        }
+    }
 }
 
 impl fmt::Display for TokenPosition {
@@ -21,15 +36,191 @@ impl fmt::Display for TokenPosition {
     }
 }
 
+/// Errors reported by the scanner
+#[derive(PartialEq, Debug, Clone)]
+pub enum ScanError {
+    /// Text which does not match any of the tokenization rules
+    UnknownToken(String),
+    /// The stream is too long for its positions to be represented
+    PositionOverflow,
+    /// An identifier longer than the configured maximum
+    IdentifierTooLong(String),
+    /// A char literal without a character, `''`
+    EmptyCharLiteral,
+    /// A char literal without a closing quote
+    UnterminatedChar,
+    /// Bytes which are not valid UTF-8
+    InvalidUtf8,
+    /// A `#define` line without a valid name
+    InvalidDefine(String),
+    /// A define which directly or indirectly expands to itself
+    RecursiveDefine(String),
+    /// A binary operator without whitespace on both sides, in strict spacing mode
+    MissingOperatorSpace(String),
+    /// The source has more tokens than the configured maximum
+    TooManyTokens(usize),
+    /// Reading the source failed, the scan stopped at this line
+    Io(String),
+}
+
+impl ScanError {
+    /// Gets the stable code of the error, such as `E001` for an unknown token
+    ///
+    /// Codes never change meaning, so they can be used to look up documentation or translated messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ScanError::UnknownToken(_) => "E001",
+            ScanError::PositionOverflow => "E002",
+            ScanError::IdentifierTooLong(_) => "E003",
+            ScanError::EmptyCharLiteral => "E004",
+            ScanError::UnterminatedChar => "E005",
+            ScanError::InvalidUtf8 => "E006",
+            ScanError::InvalidDefine(_) => "E007",
+            ScanError::RecursiveDefine(_) => "E008",
+            ScanError::MissingOperatorSpace(_) => "E009",
+            ScanError::TooManyTokens(_) => "E010",
+            ScanError::Io(_) => "E011",
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::UnknownToken(s) => write!(f, "unknown token '{}'", s),
+            ScanError::PositionOverflow => write!(f, "position overflow"),
+            ScanError::IdentifierTooLong(s) => write!(f, "identifier '{}' is too long", s),
+            ScanError::EmptyCharLiteral => write!(f, "empty char literal"),
+            ScanError::UnterminatedChar => write!(f, "unterminated char literal"),
+            ScanError::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ScanError::InvalidDefine(s) => write!(f, "invalid define '{}'", s),
+            ScanError::RecursiveDefine(s) => write!(f, "define '{}' expands to itself", s),
+            ScanError::MissingOperatorSpace(s) => write!(f, "missing space around '{}'", s),
+            ScanError::TooManyTokens(max) => write!(f, "more than {} tokens", max),
+            ScanError::Io(s) => write!(f, "read error: {}", s),
+        }
+    }
+}
+
+/// Numeric token types
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum NumberKind {
+    Int,
+    Float,
+}
+
+/// How far scanning is abandoned after an error, see `ScannerConfig::fail_fast`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum FailFast {
+    /// Skip the rest of the line and continue with the next one
+    Line,
+    /// Stop scanning the source
+    Source,
+}
+
+/// Options controlling the behaviour of the scanner
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ScannerConfig {
+    /// Maximum number of characters in a name, call or builtin, unlimited if `None`
+    pub max_identifier_length: Option<usize>,
+    /// Number of the first line, used when the source is embedded in a larger document
+    pub start_line: usize,
+    /// Emit a `#!` line at the very start of the source as a Shebang token instead of skipping it as a comment
+    pub keep_shebang: bool,
+    /// Emit comments as LineComment tokens instead of discarding them
+    pub keep_comments: bool,
+    /// Rewrite numeric literals towards one numeric type
+    ///
+    /// With `Int` whole-valued floats such as `3.0` become `Int(3)`,
+    /// with `Float` every integer becomes a `Float`. The latter suits float-only dialects,
+    /// where a bare `5` should scan as `Float(5.0)`.
+    pub normalize_numbers: Option<NumberKind>,
+    /// Stop after the first error instead of reporting every error on a line
+    pub fail_fast: Option<FailFast>,
+    /// Token rules to leave out of the grammar, their text is scanned by the remaining rules instead
+    ///
+    /// Only kinds produced by a rule can be disabled, `Shebang` and `LineComment` are controlled by their own options.
+    pub disabled_rules: Vec<TokenKind>,
+    /// Report positions as display columns with tabs expanded to the given width
+    ///
+    /// Every other character counts as one column. Positions are then no longer byte offsets,
+    /// so they should not be passed to the byte-based helpers in `source`.
+    pub expand_tabs: Option<usize>,
+    /// Replace the symbol of a token rule, such as `:=` for `Assign` in a Pascal-style dialect
+    ///
+    /// The symbol is matched literally and keeps the priority of the rule it replaces.
+    /// Tokens still display with their default symbols.
+    /// Only rules without a payload can be remapped, entries for literals, names, calls and builtins are ignored.
+    pub operator_symbols: Vec<(TokenKind, String)>,
+    /// Reject binary operators which are not surrounded by whitespace
+    ///
+    /// A `-` in unary position, such as `-5`, is exempt.
+    /// This applies the same rule as `lint::check_operator_spacing`, see `operators::is_binary_operator`.
+    pub require_operator_spacing: bool,
+    /// Stop with a TooManyTokens error at the first token past this many, unlimited if `None`
+    pub max_tokens: Option<usize>,
+}
+
+impl ScannerConfig {
+    /// Converts a byte offset within a line into the position reported for it
+    fn position(&self, line: &str, byte: usize) -> usize {
+        match self.expand_tabs {
+            None => byte,
+            Some(width) => line[..byte].chars().fold(0, |column, c| match c {
+                '\t' if width > 0 => (column / width + 1) * width,
+                _ => column + 1,
+            }),
+        }
+    }
+
+    /// Rewrites a numeric token according to `normalize_numbers`
+    fn normalize(&self, token: Token) -> Token {
+        match (token, self.normalize_numbers) {
+            (Token::Float(x), Some(NumberKind::Int))
+                if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 =>
+            {
+                Token::Int(x as i64)
+            }
+            (Token::Int(i), Some(NumberKind::Float)) => Token::Float(i as f64),
+            (token, _) => token,
+        }
+    }
+
+    /// Applies the configuration to a scanned token, checking it against the configured limits
+    fn apply(&self, token: Token) -> Result<Token, ScanError> {
+        let token = self.normalize(token);
+        match (&token, self.max_identifier_length) {
+            (Token::Name(s), Some(max)) | (Token::Call(s), Some(max)) | (Token::Builtin(s), Some(max))
+                if s.chars().count() > max =>
+            {
+                Err(ScanError::IdentifierTooLong(s.clone()))
+            }
+            _ => Ok(token),
+        }
+    }
+}
+
+/// Every token found during a scan, in source order
+pub type Tokens = Vec<(TokenPosition, Token)>;
+
+/// Every error found during a scan, in source order
+pub type ScanErrors = Vec<(TokenPosition, ScanError)>;
+
+/// Result of a scan, either all tokens or all errors
+pub type ScanResult = Result<Tokens, ScanErrors>;
+
 /// Tokenization rules macro
 /// 
 /// Format is
 /// [TokenName][Optional Parameters] = [Regex rule] => [Formatting],
 /// 
 /// Rules are prioritized in order
+///
+/// Tokens which the scanner produces by itself rather than through a rule
+/// may be listed after a semicolon as [TokenName][Optional Parameters],
 #[macro_export]
 macro_rules! token_rules {
-    ($($name:ident$($args:ty)? = $regex:expr => $rule:expr,)+) => {
+    ($($name:ident$(($args:ty))? = $regex:expr => $rule:expr,)+ $(; $($special:ident$(($special_args:ty))?,)+)?) => {
 
         /// Internal token types used by the scanner to tag matched regexes
         #[repr(u8)]
@@ -41,6 +232,7 @@ macro_rules! token_rules {
              * By encoding errors as a token we can continue parsing 
              * in order to capture as many scanner errors as possible in one go
              */
+            EmptyChar,
             Error
         }
 
@@ -55,9 +247,37 @@ macro_rules! token_rules {
         }
 
         /// Tokens exposed by the scanner after a successfull scan
-        #[derive(Debug, PartialEq)]
+        #[derive(Debug, PartialEq, Clone)]
         pub enum Token {
             $($name$(($args))?, )+
+            $($($special$(($special_args))?, )+)?
+        }
+
+        /// The kind of a token, without any payload
+        #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+        pub enum TokenKind {
+            $($name, )+
+            $($($special, )+)?
+        }
+
+        impl Token {
+            /// Gets the kind of the token
+            pub fn kind(&self) -> TokenKind {
+                match self {
+                    $(Token::$name { .. } => TokenKind::$name, )+
+                    $($(Token::$special { .. } => TokenKind::$special, )+)?
+                }
+            }
+        }
+
+        impl TokenKind {
+            /// Whether tokens of this kind carry a payload parsed from their text
+            fn has_payload(self) -> bool {
+                match self {
+                    $(TokenKind::$name => !stringify!($($args)?).is_empty(), )+
+                    $($(TokenKind::$special => !stringify!($($special_args)?).is_empty(), )+)?
+                }
+            }
         }
 
         /// Takes a regex of alternations and a string and converts it into a vector of disjoint matches
@@ -77,70 +297,608 @@ macro_rules! token_rules {
             matches
         }
 
-        /// Returns a vector of tokens from a BufRead.
-        /// The tokenizer takes the buffer and splits it into tokens as defined in this macro.
-        pub fn tokenize<R: BufRead>(buf_reader: &mut R) -> Result<Vec<(TokenPosition, Token)>, Vec<(TokenPosition, String)>> {
-
-            // Separate recording of valid tokens and errors allows for easy handling later
-            let mut tokens: Vec<(TokenPosition, Token)> = Vec::new();
-            let mut errors: Vec<(TokenPosition, String)> = Vec::new();
-
-            // Regex responsible for parsing the lines
-            let re = Regex::new(&concat!($("|(",$regex,")",)+r"|(\S+)")[1..]).expect("Invalid regex");
-
-            for (line_num, line) in buf_reader.lines().enumerate() {
-                let line = line.as_ref().unwrap().split("#").next().unwrap();
-                for (token_type, cap) in find_matches(&re, &line) {
-
-                    match token_type {
-                        /*
-                         * The macro expands into a complete pattern-match of
-                         * all defined tokens.
-                         */
-                        $(TokenType::$name =>
-                            {
-                                use Token::*;
-                                tokens.push((
-                                    TokenPosition {
-                                    line: line_num,
-                                    position: cap.start()
-                                },
-                                $rule(&line[cap.start()..cap.end()])
-                                ));
-                            }
-                        ,)+
-                        /*
-                         * Since the Error token is special to the scanner and may result in
-                         * scanner failure it is handled separately
-                         */
-                        TokenType::Error => {
-                            errors.push((
-                                TokenPosition {
-                                    line: line_num,
-                                    position: cap.start()
-                                },
-                                line[cap.start()..cap.end()].to_string()
-                            ));
-                        }
-                    };
+        /// Builds the regex of alternations responsible for parsing the lines
+        ///
+        /// Disabled rules keep their capture group so that capture indices still map onto `TokenType`,
+        /// but are replaced by a pattern which never matches. Remapped symbols keep the priority of their rule,
+        /// and remappings of rules with a payload are ignored since their closures parse the original syntax.
+        fn token_regex(config: &ScannerConfig) -> Regex {
+            let rules: Vec<String> = [$((TokenKind::$name, $regex),)+]
+                .iter()
+                .map(|(kind, regex)| {
+                    let symbol = config.operator_symbols.iter().find(|(k, _)| k == kind && !k.has_payload());
+                    match symbol {
+                        _ if config.disabled_rules.contains(kind) => r"(\b\B)".to_string(),
+                        Some((_, symbol)) => format!("({})", regex::escape(symbol)),
+                        None => format!("({})", regex),
+                    }
+                })
+                .collect();
+            Regex::new(&format!(r"{}|('')|(\S+)", rules.join("|"))).expect("Invalid regex")
+        }
+
+        /// Converts the text matched by a rule into a token
+        fn make_token(token_type: TokenType, text: &str) -> Result<Token, ScanError> {
+            match token_type {
+                /*
+                 * The macro expands into a complete pattern-match of
+                 * all defined tokens.
+                 */
+                $(TokenType::$name => {
+                    use Token::*;
+                    Ok($rule(text))
+                },)+
+                /*
+                 * Since the Error token is special to the scanner and may result in
+                 * scanner failure it is handled separately
+                 */
+                TokenType::EmptyChar => Err(ScanError::EmptyCharLiteral),
+                TokenType::Error => Err(ScanError::UnknownToken(text.to_string())),
+            }
+        }
+    };
+}
+
+/// Scans a single line, handing every token or error to `f`.
+///
+/// `previous` holds the kind of the last code token handed to `f`, or `None` after an error,
+/// and is carried across lines so that strict spacing sees where an operand ended.
+/// Returns false if `f` asked to stop scanning.
+fn scan_line<F>(re: &Regex, config: &ScannerConfig, line_num: usize, line: &str, previous: &mut Option<TokenKind>, f: &mut F) -> bool
+where
+    F: FnMut(TokenPosition, Result<Token, ScanError>, &str) -> bool,
+{
+    if config.keep_shebang && line_num == config.start_line && line.starts_with("#!") {
+        let pos = TokenPosition {
+            line: line_num,
+            position: 0,
+            length: line.len(),
+        };
+        return f(pos, Ok(Token::Shebang(line[2..].to_string())), line);
+    }
+
+    let mut parts = line.splitn(2, '#');
+    let code = parts.next().unwrap();
+    let matches = find_matches(re, code);
+    let starts: Vec<usize> = matches.iter().map(|(_, cap)| cap.start()).collect();
+    let mut previous_end: Option<usize> = None;
+
+    for (i, (token_type, cap)) in matches.into_iter().enumerate() {
+        let pos = TokenPosition {
+            line: line_num,
+            position: config.position(line, cap.start()),
+            length: cap.end() - cap.start(),
+        };
+        let mut result = make_token(token_type, cap.as_str()).and_then(|token| config.apply(token));
+
+        if config.require_operator_spacing {
+            if let Ok(token) = &result {
+                let spaced_before = previous_end.is_none_or(|end| end < cap.start());
+                let spaced_after = starts.get(i + 1).is_none_or(|start| cap.end() < *start);
+                let unary = token.kind() == TokenKind::Sub && is_unary_position(*previous);
+                if is_binary_operator(token.kind()) && !unary && !(spaced_before && spaced_after) {
+                    result = Err(ScanError::MissingOperatorSpace(cap.as_str().to_string()));
                 }
             }
+        }
+        previous_end = Some(cap.end());
+        *previous = result.as_ref().ok().map(Token::kind);
 
-            if !errors.is_empty() {
-                return Err(errors);
+        let failed = result.is_err();
+        if !f(pos, result, cap.as_str()) {
+            return false;
+        }
+        if failed {
+            if let Some(fail_fast) = config.fail_fast {
+                return fail_fast == FailFast::Line;
             }
+        }
+    }
 
-            Ok(tokens)
+    match parts.next() {
+        Some(comment) if config.keep_comments => {
+            let pos = TokenPosition {
+                line: line_num,
+                position: config.position(line, code.len()),
+                length: comment.len() + 1,
+            };
+            f(pos, Ok(Token::LineComment(comment.to_string())), &line[code.len()..])
         }
+        _ => true,
+    }
+}
+
+/// Scans a single line given as bytes, see `scan_line`.
+///
+/// The line is validated as UTF-8 here. An invalid sequence is reported as an error
+/// and the rest of the line is skipped, unless the sequence is inside a comment.
+fn scan_line_bytes<F>(
+    re: &Regex,
+    config: &ScannerConfig,
+    line_num: usize,
+    line: &[u8],
+    previous: &mut Option<TokenKind>,
+    f: &mut F,
+) -> bool
+where
+    F: FnMut(TokenPosition, Result<Token, ScanError>, &str) -> bool,
+{
+    let e = match str::from_utf8(line) {
+        Ok(line) => return scan_line(re, config, line_num, line, previous, f),
+        Err(e) => e,
     };
+
+    let valid = e.valid_up_to();
+    // The prefix is valid UTF-8 by definition
+    let prefix = str::from_utf8(&line[..valid]).unwrap();
+    if !scan_line(re, config, line_num, prefix, previous, f) {
+        return false;
+    }
+
+    // Only report the invalid sequence if it is not inside a comment
+    if prefix.contains('#') {
+        return true;
+    }
+    let pos = TokenPosition {
+        line: line_num,
+        position: config.position(prefix, valid),
+        length: e.error_len().unwrap_or(line.len() - valid),
+    };
+    *previous = None;
+    if !f(pos, Err(ScanError::InvalidUtf8), "") {
+        return false;
+    }
+    config.fail_fast != Some(FailFast::Source)
 }
 
-/// Tokenization rules
-/// 
-/// Format is
-/// [TokenName][Optional Parameters] = [Regex rule] => [Formatting],
-/// 
-/// Rules are prioritized in order
+/// Scans a BufRead line by line.
+///
+/// Every token or error is handed to `f` in source order together with the text it was scanned from.
+/// Scanning stops early if `f` returns false.
+fn scan<R, F>(buf_reader: &mut R, config: &ScannerConfig, f: F)
+where
+    R: BufRead,
+    F: FnMut(TokenPosition, Result<Token, ScanError>, &str) -> bool,
+{
+    scan_with(&token_regex(config), buf_reader, config, f)
+}
+
+/// Scans a BufRead line by line with an already compiled token regex, see `scan`.
+fn scan_with<R, F>(re: &Regex, buf_reader: &mut R, config: &ScannerConfig, f: F)
+where
+    R: BufRead,
+    F: FnMut(TokenPosition, Result<Token, ScanError>, &str) -> bool,
+{
+    scan_lines_with(re, buf_reader, config, |_| (), f)
+}
+
+/// Scans a BufRead like `scan_with`, handing every line to `on_line` before it is scanned.
+///
+/// Lines are passed as read, without the line ending.
+fn scan_lines_with<R, L, F>(re: &Regex, buf_reader: &mut R, config: &ScannerConfig, mut on_line: L, mut f: F)
+where
+    R: BufRead,
+    L: FnMut(&[u8]),
+    F: FnMut(TokenPosition, Result<Token, ScanError>, &str) -> bool,
+{
+    let mut count: usize = 0;
+    let mut f = |pos, result: Result<Token, ScanError>, text: &str| match (&result, config.max_tokens) {
+        (Ok(_), Some(max)) if count == max => {
+            f(pos, Err(ScanError::TooManyTokens(max)), text);
+            false
+        }
+        (Ok(_), _) => {
+            count += 1;
+            f(pos, result, text)
+        }
+        _ => f(pos, result, text),
+    };
+
+    // The line counter is advanced with checked arithmetic so that it never silently wraps
+    let mut next_line = Some(config.start_line);
+    let mut previous = None;
+
+    for line in buf_reader.split(b'\n') {
+        // A read error ends the scan, it is reported at the line which could not be read
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                let pos = TokenPosition {
+                    line: next_line.unwrap_or(usize::MAX),
+                    position: 0,
+                    length: 0,
+                };
+                f(pos, Err(ScanError::Io(e.to_string())), "");
+                return;
+            }
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+
+        let line_num = match next_line {
+            Some(line_num) => line_num,
+            None => {
+                let pos = TokenPosition {
+                    line: usize::MAX,
+                    position: 0,
+                    length: 0,
+                };
+                f(pos, Err(ScanError::PositionOverflow), "");
+                return;
+            }
+        };
+        next_line = line_num.checked_add(1);
+        on_line(line);
+
+        if !scan_line_bytes(re, config, line_num, line, &mut previous, &mut f) {
+            return;
+        }
+    }
+}
+
+/// Returns a vector of tokens from a BufRead.
+/// The tokenizer takes the buffer and splits it into tokens as defined in the tokenization rules.
+pub fn tokenize<R: BufRead>(buf_reader: &mut R) -> ScanResult {
+    tokenize_with_config(buf_reader, &ScannerConfig::default())
+}
+
+/// Returns a vector of tokens from a BufRead, scanned with the given configuration.
+pub fn tokenize_with_config<R: BufRead>(buf_reader: &mut R, config: &ScannerConfig) -> ScanResult {
+    collect_tokens(&token_regex(config), buf_reader, config)
+}
+
+/// Collects every token and error of a scan
+fn collect_tokens<R: BufRead>(re: &Regex, buf_reader: &mut R, config: &ScannerConfig) -> ScanResult {
+    // Separate recording of valid tokens and errors allows for easy handling later
+    let mut tokens: Vec<(TokenPosition, Token)> = Vec::new();
+    let mut errors: ScanErrors = Vec::new();
+
+    scan_with(re, buf_reader, config, |pos, result, _| {
+        match result {
+            Ok(token) => tokens.push((pos, token)),
+            Err(err) => errors.push((pos, err)),
+        }
+        true
+    });
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+/// A scanner configuration together with its compiled rules
+///
+/// Building the token regex is the most expensive part of a short scan,
+/// so a `Scanner` is worth keeping around when tokenizing many inputs.
+/// The configuration can be read back with `config` to reproduce a scan later.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    config: ScannerConfig,
+    re: Regex,
+}
+
+impl Scanner {
+    /// Compiles a scanner for the given configuration
+    pub fn from_config(config: ScannerConfig) -> Scanner {
+        let re = token_regex(&config);
+        Scanner { config, re }
+    }
+
+    /// Gets the configuration of the scanner
+    pub fn config(&self) -> &ScannerConfig {
+        &self.config
+    }
+
+    /// Returns a vector of tokens from a BufRead, see `tokenize_with_config`
+    pub fn tokenize<R: BufRead>(&self, buf_reader: &mut R) -> ScanResult {
+        collect_tokens(&self.re, buf_reader, &self.config)
+    }
+
+    /// Hands every token or error to `callback` as soon as it is found, see `tokenize_cb`
+    pub fn tokenize_cb<R, F>(&self, buf_reader: &mut R, mut callback: F)
+    where
+        R: BufRead,
+        F: FnMut(Result<(TokenPosition, Token), (TokenPosition, ScanError)>),
+    {
+        scan_with(&self.re, buf_reader, &self.config, |pos, result, _| {
+            callback(match result {
+                Ok(token) => Ok((pos, token)),
+                Err(err) => Err((pos, err)),
+            });
+            true
+        });
+    }
+}
+
+impl Default for Scanner {
+    fn default() -> Scanner {
+        Scanner::from_config(ScannerConfig::default())
+    }
+}
+
+/// Scans a BufRead and hands every token or error to `callback` as soon as it is found.
+///
+/// Nothing is collected, so memory use does not grow with the size of the input.
+/// Tokens and errors are passed in source order.
+pub fn tokenize_cb<R, F>(buf_reader: &mut R, callback: F)
+where
+    R: BufRead,
+    F: FnMut(Result<(TokenPosition, Token), (TokenPosition, ScanError)>),
+{
+    Scanner::default().tokenize_cb(buf_reader, callback)
+}
+
+/// Tokenizes a region of a larger document, such as an editor selection.
+///
+/// Positions are reported relative to the full document, where `base` is the position of the start of the region.
+/// Only tokens on the first line of the region are offset by the column of `base`.
+/// A token whose start or end would not fit in a `usize` is reported as a PositionOverflow.
+pub fn tokenize_region(text: &str, base: TokenPosition) -> ScanResult {
+    let mut tokens: Vec<(TokenPosition, Token)> = Vec::new();
+    let mut errors: ScanErrors = Vec::new();
+
+    let config = ScannerConfig {
+        start_line: base.line,
+        ..ScannerConfig::default()
+    };
+
+    scan(&mut text.as_bytes(), &config, |mut pos, mut result, _| {
+        if pos.line == base.line {
+            let position = pos.position.checked_add(base.position);
+            match position.filter(|position| position.checked_add(pos.length).is_some()) {
+                Some(position) => pos.position = position,
+                None => result = Err(ScanError::PositionOverflow),
+            }
+        }
+        match result {
+            Ok(token) => tokens.push((pos, token)),
+            Err(err) => errors.push((pos, err)),
+        }
+        true
+    });
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+/// Tokenizes a source as if its whitespace-only lines were removed.
+///
+/// Along with the tokens, returns a line map where entry `i` is the original line of folded line `i`,
+/// so positions can be translated back. Errors are reported on folded lines as well.
+pub fn tokenize_folding_blank_lines(source: &str) -> Result<(Tokens, Vec<usize>), ScanErrors> {
+    let line_map: Vec<usize> = source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_num, _)| line_num)
+        .collect();
+
+    // Tokens and errors only occur on lines which are not blank, so every line is in the map
+    let fold = |mut pos: TokenPosition| {
+        pos.line = line_map.binary_search(&pos.line).unwrap_or_else(|i| i);
+        pos
+    };
+
+    match tokenize(&mut source.as_bytes()) {
+        Ok(tokens) => {
+            let tokens = tokens.into_iter().map(|(pos, token)| (fold(pos), token)).collect();
+            Ok((tokens, line_map))
+        }
+        Err(errors) => Err(errors.into_iter().map(|(pos, err)| (fold(pos), err)).collect()),
+    }
+}
+
+/// Returns a vector of tokens from a byte slice.
+///
+/// The bytes are split into lines directly, and every line is validated as UTF-8 only when it is scanned.
+/// An invalid sequence is reported as an error and the rest of its line is skipped.
+/// Positions and errors are the same as for `tokenize` over the same content.
+pub fn tokenize_bytes(bytes: &[u8]) -> ScanResult {
+    let config = ScannerConfig::default();
+    let re = token_regex(&config);
+    let mut tokens: Tokens = Vec::new();
+    let mut errors: ScanErrors = Vec::new();
+    let mut previous = None;
+
+    let mut collect = |pos, result, _: &str| {
+        match result {
+            Ok(token) => tokens.push((pos, token)),
+            Err(err) => errors.push((pos, err)),
+        }
+        true
+    };
+
+    for (line_num, line) in bytes.split(|b| *b == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        scan_line_bytes(&re, &config, line_num, line, &mut previous, &mut collect);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+/// Estimates the number of tokens in a BufRead without scanning it.
+///
+/// Counts runs of letters and runs of digits plus every other non-whitespace byte outside of comments.
+/// Every token starts at such a run or byte, so the estimate is never less than the actual count,
+/// and it is usually within a small factor of it. Read errors end the estimate early.
+pub fn estimate_token_count<R: BufRead>(buf_reader: &mut R) -> usize {
+    #[derive(PartialEq, Clone, Copy)]
+    enum Class {
+        Space,
+        Word,
+        Digit,
+        Other,
+    }
+
+    let class = |b: u8| match b {
+        b' ' | b'\t' | b'\r' | b'\n' => Class::Space,
+        b'_' | b'a'..=b'z' | b'A'..=b'Z' => Class::Word,
+        b'0'..=b'9' => Class::Digit,
+        _ => Class::Other,
+    };
+
+    let mut estimate = 0;
+    for line in buf_reader.split(b'\n') {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let mut previous = Class::Space;
+        for &b in line.iter().take_while(|b| **b != b'#') {
+            let current = class(b);
+            if current != Class::Space && (current == Class::Other || current != previous) {
+                estimate += 1;
+            }
+            previous = current;
+        }
+    }
+
+    estimate
+}
+
+/// Checks whether a BufRead contains any scanner errors.
+///
+/// Cheaper than a full tokenization as no tokens are collected and scanning stops at the first error.
+pub fn has_scan_errors<R: BufRead>(buf_reader: &mut R) -> bool {
+    let mut found = false;
+
+    scan(buf_reader, &ScannerConfig::default(), |_, result, _| {
+        found = result.is_err();
+        !found
+    });
+
+    found
+}
+
+/// Returns every token before the first scanner error, together with that error.
+///
+/// Scanning stops at the first error, so this is useful for analysing input up to the point where it breaks.
+pub fn valid_prefix<R: BufRead>(buf_reader: &mut R) -> (Tokens, Option<(TokenPosition, ScanError)>) {
+    let mut tokens: Tokens = Vec::new();
+    let mut error = None;
+
+    scan(buf_reader, &ScannerConfig::default(), |pos, result, _| match result {
+        Ok(token) => {
+            tokens.push((pos, token));
+            true
+        }
+        Err(err) => {
+            error = Some((pos, err));
+            false
+        }
+    });
+
+    (tokens, error)
+}
+
+/// Tokenizes a source and tags every token with its parenthesis nesting depth.
+///
+/// Opening parentheses, calls and builtins increase the depth for the tokens following them,
+/// and a closing parenthesis is reported at the depth outside of it, the same as its opening token.
+/// Unbalanced closing parentheses never bring the depth below zero.
+/// This is meant for editors, so scanner errors are skipped rather than reported.
+pub fn tokenize_with_depth(source: &str) -> Vec<(TokenPosition, Token, usize)> {
+    let mut tokens: Vec<(TokenPosition, Token, usize)> = Vec::new();
+    let mut depth: usize = 0;
+
+    scan(&mut source.as_bytes(), &ScannerConfig::default(), |pos, result, _| {
+        match result {
+            Ok(token @ Token::Lpar) | Ok(token @ Token::Call(_)) | Ok(token @ Token::Builtin(_)) => {
+                tokens.push((pos, token, depth));
+                depth += 1;
+            }
+            Ok(Token::Rpar) => {
+                depth = depth.saturating_sub(1);
+                tokens.push((pos, Token::Rpar, depth));
+            }
+            Ok(token) => tokens.push((pos, token, depth)),
+            Err(_) => (),
+        }
+        true
+    });
+
+    tokens
+}
+
+/// Returns a vector of tokens from a BufRead, where numeric literals keep their original text.
+///
+/// The text is `Some` for `Int` and `Float` tokens, so that tools such as formatters
+/// can reproduce a number exactly as it was written instead of guessing its canonical form.
+pub fn tokenize_preserving_numbers<R: BufRead>(buf_reader: &mut R) -> Result<Vec<(TokenPosition, Token, Option<String>)>, ScanErrors> {
+    let mut tokens: Vec<(TokenPosition, Token, Option<String>)> = Vec::new();
+    let mut errors: ScanErrors = Vec::new();
+
+    scan(buf_reader, &ScannerConfig::default(), |pos, result, text| {
+        match result {
+            Ok(token @ Token::Int(_)) | Ok(token @ Token::Float(_)) => tokens.push((pos, token, Some(text.to_string()))),
+            Ok(token) => tokens.push((pos, token, None)),
+            Err(err) => errors.push((pos, err)),
+        }
+        true
+    });
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+/// Summary statistics of a source, see `source_stats`
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SourceStats {
+    /// Number of lines
+    pub lines: usize,
+    /// Length in bytes of the longest line, not counting the line ending
+    pub max_line_length: usize,
+    /// Number of tokens scanned
+    pub tokens: usize,
+    /// Number of scanner errors
+    pub errors: usize,
+}
+
+/// Collects line, token and error counts of a BufRead in a single pass.
+///
+/// No tokens are kept, so this is cheaper than tokenizing and measuring the lines separately.
+/// The counts match what `tokenize` reports, including for invalid UTF-8 and read errors.
+pub fn source_stats<R: BufRead>(buf_reader: &mut R) -> SourceStats {
+    let config = ScannerConfig::default();
+    let mut stats = SourceStats::default();
+    let (mut lines, mut max_line_length) = (0, 0);
+
+    let on_line = |line: &[u8]| {
+        lines += 1;
+        max_line_length = max_line_length.max(line.len());
+    };
+    scan_lines_with(&token_regex(&config), buf_reader, &config, on_line, |_, result, _| {
+        match result {
+            Ok(_) => stats.tokens += 1,
+            Err(_) => stats.errors += 1,
+        }
+        true
+    });
+
+    stats.lines = lines;
+    stats.max_line_length = max_line_length;
+    stats
+}
+
+// Tokenization rules
+//
+// Format is
+// [TokenName][Optional Parameters] = [Regex rule] => [Formatting],
+//
+// Rules are prioritized in order
 token_rules! {
     // Numbers
     Float(f64) = r"[[:digit:]]*\.[[:digit:]]+" => |x: &str| Float(x.parse::<f64>().unwrap()),
@@ -166,6 +924,8 @@ token_rules! {
     Not = r"!" => |_| Not,
     And = r"&&" => |_| And,
     Or = r"\|\|" => |_| Or,
+    // Pipeline, must come before the bitwise or
+    Pipe = r"\|>" => |_| Pipe,
     // Bitwise operators
     Bnot = r"~" => |_| Bnot,
     Band = r"&" => |_| Band,
@@ -182,11 +942,180 @@ token_rules! {
     Call(String) = r"[[:alpha:]][[:word:]]*\(" => |x: &str| Call(x[..x.len()-1].to_string()),
     Builtin(String) = r"_[[:alpha:]][[:word:]]*\(" => |x: &str| Builtin(x[..x.len()-1].to_string()),
     Name(String) = r"[[:alpha:]][[:word:]]*" => |x: &str| Name(x.to_string()),
+    ;
+    // Interpreter directive on the first line
+    Shebang(String),
+    // Comment running to the end of the line
+    LineComment(String),
+}
+
+impl Token {
+    /// A human readable description of the token, for use in error messages
+    pub fn describe(&self) -> &'static str {
+        use Token::*;
+        match self {
+            Float(_) => "float literal",
+            Int(_) => "integer literal",
+            Char(_) => "char literal",
+            Shr | Shl => "shift operator",
+            Equal | Neq | Leq | Geq | Less | Greater => "comparison operator",
+            Add | Sub | Mul | Div => "arithmetic operator",
+            Assign => "assignment",
+            Not | And | Or => "logical operator",
+            Bnot | Band | Bor | Xor => "bitwise operator",
+            Pipe => "pipeline operator",
+            Lambda => "lambda backslash",
+            Comma => "comma",
+            Period => "period",
+            Semicolon => "semicolon",
+            Lpar => "opening parenthesis",
+            Rpar => "closing parenthesis",
+            Call(_) => "function call",
+            Builtin(_) => "builtin call",
+            Name(_) => "name",
+            Shebang(_) => "shebang line",
+            LineComment(_) => "comment",
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    /// Formats the token as source text which scans back into the same token
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Token::*;
+        match self {
+            Float(x) => {
+                let s = x.to_string();
+                if s.contains('.') {
+                    write!(f, "{}", s)
+                } else {
+                    write!(f, "{}.0", s)
+                }
+            }
+            Int(i) => write!(f, "{}", i),
+            Char(c) => match std::char::from_u32(*c as u32) {
+                Some(c) => write!(f, "'{}'", c),
+                None => write!(f, "'?'"),
+            },
+            Shr => write!(f, ">>"),
+            Shl => write!(f, "<<"),
+            Equal => write!(f, "=="),
+            Neq => write!(f, "!="),
+            Leq => write!(f, "<="),
+            Geq => write!(f, ">="),
+            Less => write!(f, "<"),
+            Greater => write!(f, ">"),
+            Add => write!(f, "+"),
+            Sub => write!(f, "-"),
+            Mul => write!(f, "*"),
+            Div => write!(f, "/"),
+            Assign => write!(f, "="),
+            Not => write!(f, "!"),
+            And => write!(f, "&&"),
+            Or => write!(f, "||"),
+            Bnot => write!(f, "~"),
+            Band => write!(f, "&"),
+            Bor => write!(f, "|"),
+            Pipe => write!(f, "|>"),
+            Xor => write!(f, "^"),
+            Lambda => write!(f, "\\"),
+            Comma => write!(f, ","),
+            Period => write!(f, "."),
+            Semicolon => write!(f, ";"),
+            Lpar => write!(f, "("),
+            Rpar => write!(f, ")"),
+            Call(s) | Builtin(s) => write!(f, "{}(", s),
+            Name(s) => write!(f, "{}", s),
+            Shebang(s) => write!(f, "#!{}", s),
+            LineComment(s) => write!(f, "#{}", s),
+        }
+    }
+}
+
+/// A token which borrows its identifier from the source instead of owning it
+///
+/// Tokens without a string payload never allocate and are kept as they are.
+#[derive(Debug, PartialEq, Clone)]
+pub enum BorrowedToken<'a> {
+    Name(&'a str),
+    Call(&'a str),
+    Builtin(&'a str),
+    Other(Token),
+}
+
+impl<'a> BorrowedToken<'a> {
+    /// Converts into an owned token
+    pub fn to_owned(&self) -> Token {
+        match self {
+            BorrowedToken::Name(s) => Token::Name(s.to_string()),
+            BorrowedToken::Call(s) => Token::Call(s.to_string()),
+            BorrowedToken::Builtin(s) => Token::Builtin(s.to_string()),
+            BorrowedToken::Other(token) => token.clone(),
+        }
+    }
+}
+
+/// Returns a vector of tokens borrowing their identifiers from the source.
+///
+/// This avoids allocating a string for every identifier when the source outlives the tokens.
+/// Positions and errors are the same as for `tokenize`.
+pub fn tokenize_borrowed<'a>(source: &'a str) -> Result<Vec<(TokenPosition, BorrowedToken<'a>)>, ScanErrors> {
+    let re = token_regex(&ScannerConfig::default());
+    let mut tokens: Vec<(TokenPosition, BorrowedToken)> = Vec::new();
+    let mut errors: ScanErrors = Vec::new();
+
+    for (line_num, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        for (token_type, cap) in find_matches(&re, line) {
+            let pos = TokenPosition {
+                line: line_num,
+                position: cap.start(),
+                length: cap.end() - cap.start(),
+            };
+            let text = cap.as_str();
+            let token = match token_type {
+                TokenType::Name => BorrowedToken::Name(text),
+                TokenType::Call => BorrowedToken::Call(&text[..text.len() - 1]),
+                TokenType::Builtin => BorrowedToken::Builtin(&text[..text.len() - 1]),
+                token_type => match make_token(token_type, text) {
+                    Ok(token) => BorrowedToken::Other(token),
+                    Err(err) => {
+                        errors.push((pos, err));
+                        continue;
+                    }
+                },
+            };
+            tokens.push((pos, token));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{self, BufReader, Read};
+
+    /// Reader which yields `data` and then fails every further read, counting the reads
+    struct FailingReader<'a> {
+        data: &'a [u8],
+        reads: usize,
+    }
+
+    impl Read for FailingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            if self.data.is_empty() {
+                return Err(io::Error::other("device unplugged"));
+            }
+            self.data.read(buf)
+        }
+    }
 
     macro_rules! test_tokenize_ok {
         ($s:expr => $($name:expr,)*)  => {
@@ -199,7 +1128,7 @@ mod tests {
                 let mut i = 0;
                 $(
                     assert_eq!(tokens[i].1, $name);
-                    i = i + 1;
+                    i += 1;
                 )*
                 assert_eq!(i, tokens.len());
             }
@@ -209,6 +1138,7 @@ mod tests {
     macro_rules! test_tokenize_err {
         ($s:expr => $($args:expr,)*)  => {
             {
+                use ScanError::*;
                 let errs = tokenize(&mut $s.as_bytes());
                 println!("{:?}", errs);
                 assert!(errs.is_err());
@@ -216,7 +1146,7 @@ mod tests {
                 let mut i = 0;
                 $(
                     assert_eq!(errs[i].1, $args);
-                    i = i + 1;
+                    i += 1;
                 )*
                 assert_eq!(i, errs.len());
             }
@@ -254,16 +1184,29 @@ mod tests {
     #[test]
     fn test_single_err() {
         test_tokenize_err!("¤" =>
-            "¤",
+            UnknownToken("¤".to_string()),
         );
         test_tokenize_err!("error ¤" =>
-            "¤",
+            UnknownToken("¤".to_string()),
         );
         test_tokenize_err!("¤ error" =>
-            "¤",
+            UnknownToken("¤".to_string()),
         );
     }
 
+    #[test]
+    fn test_position_overflow() {
+        let config = ScannerConfig {
+            start_line: usize::MAX - 1,
+            ..ScannerConfig::default()
+        };
+        let tokens = tokenize_with_config(&mut "a\nb".as_bytes(), &config).unwrap();
+        assert_eq!(tokens[1].0, TokenPosition { line: usize::MAX, position: 0, length: 1 });
+
+        let errs = tokenize_with_config(&mut "a\nb\nc".as_bytes(), &config).unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: usize::MAX, position: 0, length: 0 }, ScanError::PositionOverflow)]);
+    }
+
     #[allow(unused_mut)]
     #[allow(unused_imports)]
     #[test]
@@ -274,4 +1217,577 @@ mod tests {
             Name("x".to_string()),
         );
     }
+
+    #[test]
+    fn test_pipe() {
+        test_tokenize_ok!("x |> f | g || h" =>
+            Name("x".to_string()),
+            Pipe,
+            Name("f".to_string()),
+            Bor,
+            Name("g".to_string()),
+            Or,
+            Name("h".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_preserving_numbers() {
+        let tokens = tokenize_preserving_numbers(&mut "007 + 1.50 + x".as_bytes()).unwrap();
+        assert_eq!(tokens[0].1, Token::Int(7));
+        assert_eq!(tokens[0].2, Some("007".to_string()));
+        assert_eq!(tokens[1].2, None);
+        assert_eq!(tokens[2].1, Token::Float(1.5));
+        assert_eq!(tokens[2].2, Some("1.50".to_string()));
+        assert_eq!(tokens[4].1, Token::Name("x".to_string()));
+        assert_eq!(tokens[4].2, None);
+    }
+
+    #[test]
+    fn test_has_scan_errors() {
+        assert!(!has_scan_errors(&mut "f = \\x.x+1;".as_bytes()));
+        assert!(has_scan_errors(&mut "x ¤".as_bytes()));
+
+        // Any read past the first line fails, so scanning must stop before it
+        let mut reader = FailingReader { data: "1 ¤ 2\n".as_bytes(), reads: 0 };
+        assert!(has_scan_errors(&mut BufReader::new(&mut reader)));
+        assert_eq!(reader.reads, 1);
+    }
+
+    #[test]
+    fn test_tokenize_region() {
+        let base = TokenPosition { line: 10, position: 4, length: 0 };
+        let tokens = tokenize_region("x + 1\ny", base.clone()).unwrap();
+        assert_eq!(tokens[0].0, TokenPosition { line: 10, position: 4, length: 1 });
+        assert_eq!(tokens[2].0, TokenPosition { line: 10, position: 8, length: 1 });
+        assert_eq!(tokens[3].0, TokenPosition { line: 11, position: 0, length: 1 });
+
+        let errs = tokenize_region("  ¤", base).unwrap_err();
+        assert_eq!(errs[0].0, TokenPosition { line: 10, position: 6, length: 2 });
+
+        let base = TokenPosition { line: 0, position: usize::MAX - 2, length: 0 };
+        assert!(tokenize_region("ab", base.clone()).is_ok());
+        let errs = tokenize_region("abc", base).unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: 0, position: 0, length: 3 }, ScanError::PositionOverflow)]);
+    }
+
+    #[test]
+    fn test_max_identifier_length() {
+        let config = ScannerConfig {
+            max_identifier_length: Some(4),
+            ..ScannerConfig::default()
+        };
+        assert!(tokenize_with_config(&mut "abcd(x) + _put(y)".as_bytes(), &config).is_ok());
+
+        let errs = tokenize_with_config(&mut "x = abcde + f(1)".as_bytes(), &config).unwrap_err();
+        assert_eq!(
+            errs,
+            vec![(
+                TokenPosition { line: 0, position: 4, length: 5 },
+                ScanError::IdentifierTooLong("abcde".to_string())
+            )]
+        );
+
+        assert!(tokenize(&mut "a_very_long_identifier".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_start_line() {
+        let config = ScannerConfig {
+            start_line: 42,
+            ..ScannerConfig::default()
+        };
+        let tokens = tokenize_with_config(&mut "x = 1\n\ny".as_bytes(), &config).unwrap();
+        let lines: Vec<usize> = tokens.iter().map(|(pos, _)| pos.line).collect();
+        assert_eq!(lines, vec![42, 42, 42, 44]);
+        assert_eq!(tokens[0].0.position, 0);
+    }
+
+    #[test]
+    fn test_empty_char() {
+        test_tokenize_err!("''" =>
+            EmptyCharLiteral,
+        );
+        test_tokenize_err!("f('') + ''''" =>
+            EmptyCharLiteral,
+            EmptyCharLiteral,
+            EmptyCharLiteral,
+        );
+        test_tokenize_ok!("'a'" =>
+            Char(97),
+        );
+
+        let errs = tokenize(&mut "x = ''".as_bytes()).unwrap_err();
+        assert_eq!(errs[0].0, TokenPosition { line: 0, position: 4, length: 2 });
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let source = "f = \\x.g(x) + _put('a') * 3.0 - 1.25 >> 2 && !y || ~z";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        let rendered: Vec<String> = tokens.iter().map(|(_, token)| token.to_string()).collect();
+        assert_eq!(rendered.join(" "), "f = \\ x . g( x ) + _put( 'a' ) * 3.0 - 1.25 >> 2 && ! y || ~ z");
+
+        let rescanned = tokenize(&mut rendered.join(" ").as_bytes()).unwrap();
+        assert_eq!(
+            rescanned.into_iter().map(|(_, t)| t).collect::<Vec<_>>(),
+            tokens.into_iter().map(|(_, t)| t).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_valid_prefix() {
+        let (tokens, error) = valid_prefix(&mut "1 + ¤ + 2 ¤".as_bytes());
+        assert_eq!(tokens.into_iter().map(|(_, t)| t).collect::<Vec<_>>(), vec![Token::Int(1), Token::Add]);
+        assert_eq!(
+            error,
+            Some((TokenPosition { line: 0, position: 4, length: 2 }, ScanError::UnknownToken("¤".to_string())))
+        );
+
+        let (tokens, error) = valid_prefix(&mut "1 + 2".as_bytes());
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn test_shebang() {
+        let source = "#!/usr/bin/env flang\nx = 1 #! not a shebang\n#!/bin/sh";
+
+        // Skipped as a comment by default
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].1, Token::Name("x".to_string()));
+
+        let config = ScannerConfig {
+            keep_shebang: true,
+            ..ScannerConfig::default()
+        };
+        let tokens = tokenize_with_config(&mut source.as_bytes(), &config).unwrap();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].1, Token::Shebang("/usr/bin/env flang".to_string()));
+        assert_eq!(tokens[0].0, TokenPosition { line: 0, position: 0, length: 20 });
+        assert_eq!(tokens[0].1.to_string(), "#!/usr/bin/env flang");
+
+        // Only recognized at the very start of the source
+        let tokens = tokenize_with_config(&mut " #!/bin/sh\nx".as_bytes(), &config).unwrap();
+        assert_eq!(tokens.len(), 1);
+    }
+
+    #[test]
+    fn test_normalize_numbers() {
+        let source = "3.0 + 2.5 + 4";
+        let scan = |normalize_numbers| {
+            let config = ScannerConfig {
+                normalize_numbers,
+                ..ScannerConfig::default()
+            };
+            tokenize_with_config(&mut source.as_bytes(), &config)
+                .unwrap()
+                .into_iter()
+                .map(|(_, token)| token)
+                .filter(|token| *token != Token::Add)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(scan(None), vec![Token::Float(3.0), Token::Float(2.5), Token::Int(4)]);
+        assert_eq!(scan(Some(NumberKind::Int)), vec![Token::Int(3), Token::Float(2.5), Token::Int(4)]);
+        assert_eq!(scan(Some(NumberKind::Float)), vec![Token::Float(3.0), Token::Float(2.5), Token::Float(4.0)]);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(Token::Rpar.describe(), "closing parenthesis");
+        assert_eq!(Token::Lambda.describe(), "lambda backslash");
+        assert_eq!(Token::Leq.describe(), "comparison operator");
+        assert_eq!(
+            format!("found `{}` ({})", Token::Rpar, Token::Rpar.describe()),
+            "found `)` (closing parenthesis)"
+        );
+    }
+
+    #[test]
+    fn test_tokenize_borrowed() {
+        let source = String::from("f = \\g x.g(_put(x)) + 1\ny");
+        let borrowed = tokenize_borrowed(&source).unwrap();
+
+        assert_eq!(borrowed[0].1, BorrowedToken::Name("f"));
+        assert_eq!(borrowed[6].1, BorrowedToken::Call("g"));
+        assert_eq!(borrowed[7].1, BorrowedToken::Builtin("_put"));
+        assert_eq!(borrowed[11].1, BorrowedToken::Other(Token::Add));
+
+        // Identifiers point into the source
+        if let BorrowedToken::Name(name) = borrowed[0].1 {
+            assert!(source.as_bytes().as_ptr_range().contains(&name.as_ptr()));
+        }
+
+        let owned = tokenize(&mut source.as_bytes()).unwrap();
+        let upgraded: Vec<(TokenPosition, Token)> =
+            borrowed.iter().map(|(pos, token)| (pos.clone(), token.to_owned())).collect();
+        assert_eq!(upgraded, owned);
+
+        assert_eq!(
+            tokenize_borrowed("x ¤").unwrap_err(),
+            tokenize(&mut "x ¤".as_bytes()).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_tokenize_bytes() {
+        let source = "f = \\x.x+1;\r\n\n  g(f(2)) # ¤ comment\n'a' ¤\n";
+        assert_eq!(tokenize_bytes(source.as_bytes()), tokenize(&mut source.as_bytes()));
+
+        let source = "f = \\x.x+1;\n  g(f(2.5))\n";
+        assert_eq!(tokenize_bytes(source.as_bytes()), tokenize(&mut source.as_bytes()));
+    }
+
+    #[test]
+    fn test_tokenize_bytes_invalid_utf8() {
+        let errs = tokenize_bytes(b"x = 1\ny = \xff\xfe 2\nz # \xff").unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: 1, position: 4, length: 1 }, ScanError::InvalidUtf8)]);
+    }
+
+    #[test]
+    fn test_tokenize_invalid_utf8() {
+        let source: &[u8] = b"x = 1\ny = \xff\xfe 2\nz # \xff";
+        let mut source_reader = source;
+        assert_eq!(tokenize(&mut source_reader), tokenize_bytes(source));
+
+        let mut seen = Vec::new();
+        tokenize_cb(&mut &b"a\n\xff\n"[..], |result| seen.push(result));
+        assert_eq!(
+            seen,
+            vec![
+                Ok((TokenPosition { line: 0, position: 0, length: 1 }, Token::Name("a".to_string()))),
+                Err((TokenPosition { line: 1, position: 0, length: 1 }, ScanError::InvalidUtf8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_config() {
+        let config = ScannerConfig {
+            fail_fast: Some(FailFast::Source),
+            ..ScannerConfig::default()
+        };
+        let errs = tokenize_with_config(&mut &b"a \xff\nb $"[..], &config).unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: 0, position: 2, length: 1 }, ScanError::InvalidUtf8)]);
+
+        let config = ScannerConfig {
+            expand_tabs: Some(8),
+            ..ScannerConfig::default()
+        };
+        let errs = tokenize_with_config(&mut &b"\tx \xff"[..], &config).unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: 0, position: 10, length: 1 }, ScanError::InvalidUtf8)]);
+    }
+
+    #[test]
+    fn test_tokenize_read_error() {
+        let mut reader = BufReader::new(FailingReader { data: b"a b\nc", reads: 0 });
+        let errs = tokenize(&mut reader).unwrap_err();
+        assert_eq!(
+            errs,
+            vec![(TokenPosition { line: 1, position: 0, length: 0 }, ScanError::Io("device unplugged".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        let source = "# doc for f\nf = \\x.x + 1 # increment\n#define X 1";
+        assert_eq!(tokenize(&mut source.as_bytes()).unwrap().len(), 8);
+
+        let config = ScannerConfig {
+            keep_comments: true,
+            ..ScannerConfig::default()
+        };
+        let tokens = tokenize_with_config(&mut source.as_bytes(), &config).unwrap();
+        let comments: Vec<&(TokenPosition, Token)> =
+            tokens.iter().filter(|(_, t)| t.kind() == TokenKind::LineComment).collect();
+        assert_eq!(
+            comments,
+            vec![
+                &(TokenPosition { line: 0, position: 0, length: 11 }, Token::LineComment(" doc for f".to_string())),
+                &(TokenPosition { line: 1, position: 13, length: 11 }, Token::LineComment(" increment".to_string())),
+                &(TokenPosition { line: 2, position: 0, length: 11 }, Token::LineComment("define X 1".to_string())),
+            ]
+        );
+        assert_eq!(tokens[0].1.to_string(), "# doc for f");
+    }
+
+    #[test]
+    fn test_source_stats() {
+        let source = "f = \\x. x * 2 # double\n\ny = f(4) $\n";
+        assert_eq!(
+            source_stats(&mut source.as_bytes()),
+            SourceStats {
+                lines: 3,
+                max_line_length: 22,
+                tokens: 13,
+                errors: 1,
+            }
+        );
+        assert_eq!(source_stats(&mut "".as_bytes()), SourceStats::default());
+
+        let stats = source_stats(&mut &b"x = 1 \xff y"[..]);
+        assert_eq!(stats, SourceStats { lines: 1, max_line_length: 9, tokens: 3, errors: 1 });
+
+        let mut reader = BufReader::new(FailingReader { data: b"a b\n", reads: 0 });
+        assert_eq!(source_stats(&mut reader), SourceStats { lines: 1, max_line_length: 3, tokens: 2, errors: 1 });
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let source = "a = $ + @\nb = ?";
+        let errors = |fail_fast| {
+            let config = ScannerConfig {
+                fail_fast,
+                ..ScannerConfig::default()
+            };
+            let errs = tokenize_with_config(&mut source.as_bytes(), &config).unwrap_err();
+            errs.into_iter().map(|(_, err)| err).collect::<Vec<ScanError>>()
+        };
+        let unknown = |s: &str| ScanError::UnknownToken(s.to_string());
+
+        assert_eq!(errors(None), vec![unknown("$"), unknown("@"), unknown("?")]);
+        assert_eq!(errors(Some(FailFast::Line)), vec![unknown("$"), unknown("?")]);
+        assert_eq!(errors(Some(FailFast::Source)), vec![unknown("$")]);
+    }
+
+    #[test]
+    fn test_disabled_rules() {
+        let scanner = Scanner::from_config(ScannerConfig {
+            disabled_rules: vec![TokenKind::Pipe, TokenKind::Float],
+            ..ScannerConfig::default()
+        });
+        let tokens: Vec<Token> = scanner.tokenize(&mut "x |> 1.5".as_bytes()).unwrap().into_iter().map(|(_, t)| t).collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Name("x".to_string()), Token::Bor, Token::Greater, Token::Int(1), Token::Period, Token::Int(5)]
+        );
+        assert_eq!(Scanner::default().tokenize(&mut "x |> 1.5".as_bytes()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_scanner_from_config() {
+        let config = ScannerConfig {
+            max_identifier_length: Some(4),
+            start_line: 10,
+            keep_shebang: true,
+            keep_comments: true,
+            normalize_numbers: Some(NumberKind::Float),
+            fail_fast: Some(FailFast::Line),
+            disabled_rules: vec![TokenKind::Pipe],
+            expand_tabs: Some(4),
+            operator_symbols: vec![(TokenKind::Lambda, "λ".to_string())],
+            require_operator_spacing: true,
+            max_tokens: Some(8),
+        };
+        let scanner = Scanner::from_config(config.clone());
+        assert_eq!(scanner.config(), &config);
+
+        let tokens = scanner.tokenize(&mut "#!/bin/flang\n\tx = λ 2 # c".as_bytes()).unwrap();
+        assert_eq!(tokens[0], (TokenPosition { line: 10, position: 0, length: 12 }, Token::Shebang("/bin/flang".to_string())));
+        assert_eq!(tokens[1].0, TokenPosition { line: 11, position: 4, length: 1 });
+        let tokens: Vec<Token> = tokens.into_iter().skip(1).map(|(_, t)| t).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Name("x".to_string()),
+                Token::Assign,
+                Token::Lambda,
+                Token::Float(2.0),
+                Token::LineComment(" c".to_string()),
+            ]
+        );
+
+        // With Pipe disabled `|>` scans as `|` and `>`, which are not spaced in strict mode
+        let errs = scanner.tokenize(&mut "x |> f $".as_bytes()).unwrap_err();
+        assert_eq!(
+            errs,
+            vec![(TokenPosition { line: 10, position: 2, length: 1 }, ScanError::MissingOperatorSpace("|".to_string()))]
+        );
+
+        let errs = scanner.tokenize(&mut "a b c d e f g h i".as_bytes()).unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: 10, position: 16, length: 1 }, ScanError::TooManyTokens(8))]);
+
+        let errs = scanner.tokenize(&mut "longname $ @".as_bytes()).unwrap_err();
+        assert_eq!(
+            errs,
+            vec![(
+                TokenPosition { line: 10, position: 0, length: 8 },
+                ScanError::IdentifierTooLong("longname".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_with_depth() {
+        let depths: Vec<(Token, usize)> = tokenize_with_depth("f(g(x)) + y")
+            .into_iter()
+            .map(|(_, token, depth)| (token, depth))
+            .collect();
+        assert_eq!(
+            depths,
+            vec![
+                (Token::Call("f".to_string()), 0),
+                (Token::Call("g".to_string()), 1),
+                (Token::Name("x".to_string()), 2),
+                (Token::Rpar, 1),
+                (Token::Rpar, 0),
+                (Token::Add, 0),
+                (Token::Name("y".to_string()), 0),
+            ]
+        );
+
+        let depths: Vec<usize> = tokenize_with_depth("a)) (b").into_iter().map(|(_, _, depth)| depth).collect();
+        assert_eq!(depths, vec![0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        let source = "f = \\x.\n\tx +\t1 # tab\n  \ty";
+        let positions = |expand_tabs| {
+            let config = ScannerConfig {
+                expand_tabs,
+                keep_comments: true,
+                ..ScannerConfig::default()
+            };
+            tokenize_with_config(&mut source.as_bytes(), &config)
+                .unwrap()
+                .into_iter()
+                .skip(5)
+                .map(|(pos, _)| (pos.line, pos.position))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(positions(None), vec![(1, 1), (1, 3), (1, 5), (1, 7), (2, 3)]);
+        assert_eq!(positions(Some(4)), vec![(1, 4), (1, 6), (1, 8), (1, 10), (2, 4)]);
+    }
+
+    #[test]
+    fn test_operator_symbols() {
+        let scanner = Scanner::from_config(ScannerConfig {
+            operator_symbols: vec![(TokenKind::Assign, ":=".to_string()), (TokenKind::Equal, "=".to_string())],
+            ..ScannerConfig::default()
+        });
+        let kinds = |source: &str| -> Vec<TokenKind> {
+            scanner.tokenize(&mut source.as_bytes()).unwrap().iter().map(|(_, t)| t.kind()).collect()
+        };
+
+        assert_eq!(kinds("x := 1"), vec![TokenKind::Name, TokenKind::Assign, TokenKind::Int]);
+        assert_eq!(kinds("x = 1"), vec![TokenKind::Name, TokenKind::Equal, TokenKind::Int]);
+        assert_eq!(kinds("x <= 1"), vec![TokenKind::Name, TokenKind::Leq, TokenKind::Int]);
+    }
+
+    #[test]
+    fn test_estimate_token_count() {
+        for source in &["f = \\g x.g(g(x))", "x1 = 3.25 * _sqrt(y) # comment\n'a' != 'b'", "2x + y_2", ""] {
+            let actual = tokenize(&mut source.as_bytes()).unwrap().len();
+            let estimate = estimate_token_count(&mut source.as_bytes());
+            assert!(estimate >= actual, "{}: {} < {}", source, estimate, actual);
+            assert!(estimate <= 3 * actual, "{}: {} > 3 * {}", source, estimate, actual);
+        }
+    }
+
+    #[test]
+    fn test_tokenize_folding_blank_lines() {
+        let (tokens, line_map) = tokenize_folding_blank_lines("a = 1\n\n  \nb = a\n\t\n# note\nc").unwrap();
+        let lines: Vec<usize> = tokens.iter().map(|(pos, _)| pos.line).collect();
+        assert_eq!(lines, vec![0, 0, 0, 1, 1, 1, 3]);
+        assert_eq!(line_map, vec![0, 3, 5, 6]);
+        assert_eq!(line_map[tokens[6].0.line], 6);
+
+        let errs = tokenize_folding_blank_lines("\n\nx $").unwrap_err();
+        assert_eq!(errs[0].0.line, 0);
+    }
+
+    #[test]
+    fn test_require_operator_spacing() {
+        let config = ScannerConfig {
+            require_operator_spacing: true,
+            ..ScannerConfig::default()
+        };
+        let scan = |source: &str| tokenize_with_config(&mut source.as_bytes(), &config);
+
+        assert_eq!(
+            scan("1+2").unwrap_err(),
+            vec![(
+                TokenPosition { line: 0, position: 1, length: 1 },
+                ScanError::MissingOperatorSpace("+".to_string())
+            )]
+        );
+        assert_eq!(scan("a <=b").unwrap_err()[0].1, ScanError::MissingOperatorSpace("<=".to_string()));
+        assert!(scan("1 + 2").is_ok());
+        assert!(scan("-5").is_ok());
+        assert!(scan("x = -5 * (-y) + f(!z)").is_ok());
+        assert!(scan("x-5").is_err());
+        assert_eq!(
+            scan("x = a\n  -b").unwrap_err(),
+            vec![(
+                TokenPosition { line: 1, position: 2, length: 1 },
+                ScanError::MissingOperatorSpace("-".to_string())
+            )]
+        );
+        assert!(scan("x = a\n  - b").is_ok());
+        let with_comments = ScannerConfig {
+            keep_comments: true,
+            ..config.clone()
+        };
+        assert_eq!(
+            tokenize_with_config(&mut "x = a # c\n  -b".as_bytes(), &with_comments).unwrap_err(),
+            scan("x = a # c\n  -b").unwrap_err()
+        );
+        assert!(scan("x = a # c\n  -b").is_err());
+        assert_eq!(scan("x|>f").unwrap_err()[0].1, ScanError::MissingOperatorSpace("|>".to_string()));
+        assert!(tokenize(&mut "1+2".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_tokenize_cb() {
+        let mut seen = Vec::new();
+        tokenize_cb(&mut "a $ b\n''".as_bytes(), |result| match result {
+            Ok((pos, token)) => seen.push((pos.line, pos.position, token.to_string())),
+            Err((pos, err)) => seen.push((pos.line, pos.position, err.to_string())),
+        });
+        assert_eq!(
+            seen,
+            vec![
+                (0, 0, "a".to_string()),
+                (0, 2, "unknown token '$'".to_string()),
+                (0, 4, "b".to_string()),
+                (1, 0, "empty char literal".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let config = ScannerConfig {
+            max_tokens: Some(4),
+            ..ScannerConfig::default()
+        };
+        assert_eq!(tokenize_with_config(&mut "a = 1;".as_bytes(), &config).unwrap().len(), 4);
+        assert_eq!(
+            tokenize_with_config(&mut "a = 1;\nb = 2;".as_bytes(), &config).unwrap_err(),
+            vec![(TokenPosition { line: 1, position: 0, length: 1 }, ScanError::TooManyTokens(4))]
+        );
+    }
+
+    #[test]
+    fn test_operator_symbols_payload_rules() {
+        let scanner = Scanner::from_config(ScannerConfig {
+            operator_symbols: vec![
+                (TokenKind::Int, "one".to_string()),
+                (TokenKind::Call, "λ".to_string()),
+                (TokenKind::Name, "@".to_string()),
+                (TokenKind::Add, "plus".to_string()),
+            ],
+            ..ScannerConfig::default()
+        });
+        let tokens: Vec<Token> = scanner.tokenize(&mut "one plus f(1)".as_bytes()).unwrap().into_iter().map(|(_, t)| t).collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Name("one".to_string()), Token::Add, Token::Call("f".to_string()), Token::Int(1), Token::Rpar]
+        );
+        assert!(scanner.tokenize(&mut "λ @".as_bytes()).is_err());
+    }
 }
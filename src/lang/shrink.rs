@@ -0,0 +1,78 @@
+//! Reduction of failing inputs.
+//!
+//! When a token sequence triggers a bug, a smaller sequence which still
+//! triggers it makes for a far more useful bug report.
+
+use super::scanner::Token;
+
+/// Shrinks a failing token sequence while `still_fails` keeps holding, by delta debugging
+///
+/// The result is 1-minimal, removing any single token from it makes the predicate false.
+/// `still_fails` is expected to hold for the original sequence.
+pub fn shrink_tokens<F: Fn(&[Token]) -> bool>(tokens: Vec<Token>, still_fails: F) -> Vec<Token> {
+    if still_fails(&[]) {
+        return Vec::new();
+    }
+
+    let mut tokens = tokens;
+    let mut chunks = 2;
+
+    while tokens.len() >= 2 {
+        let chunk = tokens.len().div_ceil(chunks);
+        let mut reduced = false;
+
+        for start in (0..tokens.len()).step_by(chunk) {
+            let end = (start + chunk).min(tokens.len());
+            let candidate: Vec<Token> = tokens[..start].iter().chain(&tokens[end..]).cloned().collect();
+            if still_fails(&candidate) {
+                tokens = candidate;
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+
+        if !reduced {
+            if chunks >= tokens.len() {
+                break;
+            }
+            chunks = (chunks * 2).min(tokens.len());
+        }
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_shrink_tokens() {
+        let tokens: Vec<Token> = tokenize(&mut "x = 1 + y / (z - 0) * 2".as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect();
+
+        // Pretend that a division anywhere before a literal zero crashes the evaluator
+        let still_fails = |tokens: &[Token]| match tokens.iter().position(|t| *t == Token::Div) {
+            Some(i) => tokens[i..].contains(&Token::Int(0)),
+            None => false,
+        };
+
+        let shrunk = shrink_tokens(tokens, still_fails);
+        assert_eq!(shrunk, vec![Token::Div, Token::Int(0)]);
+        for i in 0..shrunk.len() {
+            let mut smaller = shrunk.clone();
+            smaller.remove(i);
+            assert!(!still_fails(&smaller));
+        }
+    }
+
+    #[test]
+    fn test_shrink_tokens_always_failing() {
+        assert!(shrink_tokens(vec![Token::Add, Token::Sub], |_| true).is_empty());
+    }
+}
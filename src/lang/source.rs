@@ -0,0 +1,225 @@
+//! Helpers for relating token positions back to the original source text.
+//!
+//! Token positions are recorded as a line and a byte position within that line,
+//! which matches how the scanner reads its input. Tools such as formatters
+//! need to get from those positions back to the raw text.
+
+use super::scanner::{ScanError, Token, TokenPosition};
+use std::io::Read;
+use std::ops::Range;
+
+/// Byte offsets of the start of every line in a source
+///
+/// Converting a position with `byte_offset` scans the source from the start.
+/// Tools which convert many positions should build an index once and use its methods instead.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    /// Indexes the line starts of a source
+    pub fn new(source: &'a str) -> LineIndex<'a> {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { source, starts }
+    }
+
+    /// Converts a token position into a byte offset into the full source, see `byte_offset`
+    pub fn byte_offset(&self, pos: &TokenPosition) -> Option<usize> {
+        let offset = self.starts.get(pos.line)?.checked_add(pos.position)?;
+
+        if offset > self.source.len() {
+            return None;
+        }
+
+        Some(offset)
+    }
+
+    /// Returns the literal text between two tokens, see `gap_between`
+    pub fn gap_between(&self, a_end: &TokenPosition, b_start: &TokenPosition) -> &'a str {
+        match (self.byte_offset(a_end), self.byte_offset(b_start)) {
+            (Some(start), Some(end)) if start <= end => self.source.get(start..end).unwrap_or(""),
+            _ => "",
+        }
+    }
+}
+
+/// Converts a token position into a byte offset into the full source
+///
+/// Returns `None` if the position lies outside of the source
+pub fn byte_offset(source: &str, pos: &TokenPosition) -> Option<usize> {
+    LineIndex::new(source).byte_offset(pos)
+}
+
+/// Finds the token covering a byte offset into the full source
+///
+/// Returns `None` if the offset is outside of the source or falls on whitespace or a comment.
+pub fn token_at_byte<'a>(
+    source: &str,
+    tokens: &'a [(TokenPosition, Token)],
+    byte_offset: usize,
+) -> Option<&'a (TokenPosition, Token)> {
+    let before = source.get(..byte_offset)?;
+    let line = before.matches('\n').count();
+    let position = byte_offset - before.rfind('\n').map_or(0, |i| i + 1);
+
+    tokens
+        .iter()
+        .find(|(pos, _)| pos.line == line && pos.position <= position && position < pos.end().position)
+}
+
+/// Returns the literal text between the end of one token and the start of the next
+///
+/// The gap contains whitespace and comments, and may span several lines.
+/// An empty string is returned if the positions are out of order or outside of the source.
+pub fn gap_between<'a>(source: &'a str, a_end: &TokenPosition, b_start: &TokenPosition) -> &'a str {
+    LineIndex::new(source).gap_between(a_end, b_start)
+}
+
+/// Replaces a byte range of the source, leaving all other text untouched
+///
+/// The span is usually derived from token positions with `byte_offset`, so that
+/// formatting and comments around an edited expression are preserved.
+///
+/// # Panics
+///
+/// Panics if the span is out of bounds or does not lie on char boundaries.
+pub fn patch_source(source: &str, expr_span: Range<usize>, replacement: &str) -> String {
+    let mut patched = source.to_string();
+    patched.replace_range(expr_span, replacement);
+    patched
+}
+
+/// Line ending convention of a source
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    /// Both conventions occur
+    Mixed,
+}
+
+/// Detects which line endings a source uses
+///
+/// A source without any line breaks is reported as `Lf`.
+/// If reading fails, the bytes read up to that point decide.
+pub fn detect_line_endings<R: Read>(mut reader: R) -> LineEnding {
+    let mut bytes = Vec::new();
+    let _ = reader.read_to_end(&mut bytes);
+
+    let mut lf = false;
+    let mut crlf = false;
+    for (i, _) in bytes.iter().enumerate().filter(|(_, b)| **b == b'\n') {
+        if i > 0 && bytes[i - 1] == b'\r' {
+            crlf = true;
+        } else {
+            lf = true;
+        }
+    }
+
+    match (lf, crlf) {
+        (true, true) => LineEnding::Mixed,
+        (false, true) => LineEnding::CrLf,
+        _ => LineEnding::Lf,
+    }
+}
+
+/// Checks that every char literal quote in the source is closed on the same line
+///
+/// This is a fast pre-check which reports the first unterminated quote without tokenizing.
+/// Quotes inside comments are ignored.
+pub fn check_quotes(source: &str) -> Result<(), (TokenPosition, ScanError)> {
+    for (line_num, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap();
+        let quotes: Vec<usize> = code.match_indices('\'').map(|(i, _)| i).collect();
+
+        if quotes.len() % 2 == 1 {
+            let pos = TokenPosition {
+                line: line_num,
+                position: quotes[quotes.len() - 1],
+                length: code.len() - quotes[quotes.len() - 1],
+            };
+            return Err((pos, ScanError::UnterminatedChar));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_gap_spaces() {
+        let source = "a   =\t1";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(gap_between(source, &tokens[0].0.end(), &tokens[1].0), "   ");
+        assert_eq!(gap_between(source, &tokens[1].0.end(), &tokens[2].0), "\t");
+    }
+
+    #[test]
+    fn test_gap_lines() {
+        let source = "f = \\x. # comment\n  x+1";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(gap_between(source, &tokens[4].0.end(), &tokens[5].0), " # comment\n  ");
+        assert_eq!(gap_between(source, &tokens[5].0.end(), &tokens[6].0), "");
+    }
+
+    #[test]
+    fn test_gap_out_of_order() {
+        let source = "a b";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(gap_between(source, &tokens[1].0, &tokens[0].0), "");
+    }
+
+    #[test]
+    fn test_line_index() {
+        let source = "a\n\nbc";
+        let index = LineIndex::new(source);
+        assert_eq!(index.byte_offset(&TokenPosition { line: 2, position: 1, length: 1 }), Some(4));
+        assert_eq!(index.byte_offset(&TokenPosition { line: 3, position: 0, length: 0 }), None);
+        assert_eq!(index.byte_offset(&TokenPosition { line: 2, position: 3, length: 0 }), None);
+        let pos = TokenPosition { line: 1, position: 0, length: 0 };
+        assert_eq!(index.byte_offset(&pos), byte_offset(source, &pos));
+    }
+
+    #[test]
+    fn test_check_quotes() {
+        assert_eq!(check_quotes("a = 'x'\nb = f('y', 'z') # don't"), Ok(()));
+        assert_eq!(
+            check_quotes("a = 'x'\nb = 'y + 1\nc = 'z"),
+            Err((TokenPosition { line: 1, position: 4, length: 6 }, ScanError::UnterminatedChar))
+        );
+    }
+
+    #[test]
+    fn test_patch_source() {
+        let source = "1 + 2 * 3 # product";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        let start = byte_offset(source, &tokens[2].0).unwrap();
+        let end = byte_offset(source, &tokens[4].0.end()).unwrap();
+        assert_eq!(patch_source(source, start..end, "6"), "1 + 6 # product");
+    }
+
+    #[test]
+    fn test_detect_line_endings() {
+        assert_eq!(detect_line_endings("a = 1\nb = 2\n".as_bytes()), LineEnding::Lf);
+        assert_eq!(detect_line_endings("a = 1\r\nb = 2\r\n".as_bytes()), LineEnding::CrLf);
+        assert_eq!(detect_line_endings("a = 1\r\nb = 2\n".as_bytes()), LineEnding::Mixed);
+        assert_eq!(detect_line_endings("a = 1".as_bytes()), LineEnding::Lf);
+    }
+
+    #[test]
+    fn test_token_at_byte() {
+        let source = "f = \\x.\n  g(x) + 10";
+        let tokens = tokenize(&mut source.as_bytes()).unwrap();
+        assert_eq!(token_at_byte(source, &tokens, 11).map(|(_, t)| t), Some(&Token::Call("g".to_string())));
+        assert_eq!(token_at_byte(source, &tokens, 18).map(|(_, t)| t), Some(&Token::Int(10)));
+        assert_eq!(token_at_byte(source, &tokens, 1), None);
+        assert_eq!(token_at_byte(source, &tokens, 100), None);
+    }
+}
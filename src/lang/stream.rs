@@ -0,0 +1,177 @@
+//! An owned sequence of positioned tokens.
+//!
+//! Rewrite passes and concatenation of token sequences read more naturally
+//! with a dedicated type that can be collected into and extended.
+
+use super::scanner::{ScanError, ScanErrors, ScanResult, Token, TokenPosition};
+use std::iter::FromIterator;
+use std::slice;
+use std::vec;
+
+/// A sequence of tokens with their positions
+#[derive(Debug, PartialEq, Default)]
+pub struct TokenStream {
+    tokens: Vec<(TokenPosition, Token)>,
+}
+
+impl TokenStream {
+    /// Creates an empty stream
+    pub fn new() -> TokenStream {
+        TokenStream { tokens: Vec::new() }
+    }
+
+    /// The tokens of the stream in order
+    pub fn as_slice(&self) -> &[(TokenPosition, Token)] {
+        &self.tokens
+    }
+
+    /// Number of tokens in the stream
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Whether the stream contains no tokens
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Iterates over the tokens of the stream in order
+    pub fn iter(&self) -> slice::Iter<'_, (TokenPosition, Token)> {
+        self.tokens.iter()
+    }
+}
+
+impl From<Vec<(TokenPosition, Token)>> for TokenStream {
+    fn from(tokens: Vec<(TokenPosition, Token)>) -> TokenStream {
+        TokenStream { tokens }
+    }
+}
+
+impl From<TokenStream> for Vec<(TokenPosition, Token)> {
+    fn from(stream: TokenStream) -> Vec<(TokenPosition, Token)> {
+        stream.tokens
+    }
+}
+
+impl FromIterator<(TokenPosition, Token)> for TokenStream {
+    fn from_iter<I: IntoIterator<Item = (TokenPosition, Token)>>(iter: I) -> TokenStream {
+        TokenStream {
+            tokens: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<(TokenPosition, Token)> for TokenStream {
+    fn extend<I: IntoIterator<Item = (TokenPosition, Token)>>(&mut self, iter: I) {
+        self.tokens.extend(iter)
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = (TokenPosition, Token);
+    type IntoIter = vec::IntoIter<(TokenPosition, Token)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TokenStream {
+    type Item = &'a (TokenPosition, Token);
+    type IntoIter = slice::Iter<'a, (TokenPosition, Token)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.iter()
+    }
+}
+
+/// Concatenates token streams scanned from separate snippets
+///
+/// Each stream is paired with the line at which its snippet starts in the assembled program,
+/// and the lines of its tokens are offset by that amount.
+/// A token whose line cannot be represented is reported as a PositionOverflow error.
+pub fn concat_streams(streams: Vec<(usize, Vec<(TokenPosition, Token)>)>) -> ScanResult {
+    let mut tokens: Vec<(TokenPosition, Token)> = Vec::new();
+    let mut errors: ScanErrors = Vec::new();
+
+    for (start_line, stream) in streams {
+        for (mut pos, token) in stream {
+            match pos.line.checked_add(start_line) {
+                Some(line) => {
+                    pos.line = line;
+                    tokens.push((pos, token));
+                }
+                None => errors.push((pos, ScanError::PositionOverflow)),
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::scanner::tokenize;
+
+    #[test]
+    fn test_collect_and_extend() {
+        let first = tokenize(&mut "a = 1;".as_bytes()).unwrap();
+        let second = tokenize(&mut "\nb = a".as_bytes()).unwrap();
+
+        let mut stream: TokenStream = first.into_iter().collect();
+        assert_eq!(stream.len(), 4);
+
+        stream.extend(second);
+        assert_eq!(stream.len(), 7);
+
+        let kinds: Vec<&Token> = stream.iter().map(|(_, token)| token).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &Token::Name("a".to_string()),
+                &Token::Assign,
+                &Token::Int(1),
+                &Token::Semicolon,
+                &Token::Name("b".to_string()),
+                &Token::Assign,
+                &Token::Name("a".to_string()),
+            ]
+        );
+        assert_eq!(stream.as_slice()[4].0.line, 1);
+    }
+
+    #[test]
+    fn test_empty() {
+        let stream: TokenStream = Vec::new().into_iter().collect();
+        assert!(stream.is_empty());
+        assert_eq!(stream, TokenStream::new());
+    }
+
+    #[test]
+    fn test_concat_streams() {
+        let header = tokenize(&mut "a = 1;\nb = 2;".as_bytes()).unwrap();
+        let body = tokenize(&mut "a + b".as_bytes()).unwrap();
+
+        let tokens = concat_streams(vec![(0, header), (5, body)]).unwrap();
+        let positions: Vec<(usize, usize)> = tokens.iter().map(|(pos, _)| (pos.line, pos.position)).collect();
+        assert_eq!(
+            positions,
+            vec![(0, 0), (0, 2), (0, 4), (0, 5), (1, 0), (1, 2), (1, 4), (1, 5), (5, 0), (5, 2), (5, 4)]
+        );
+        assert_eq!(tokens[9].1, Token::Add);
+    }
+
+    #[test]
+    fn test_concat_streams_overflow() {
+        let first = tokenize(&mut "a".as_bytes()).unwrap();
+        let second = tokenize(&mut "b\nc".as_bytes()).unwrap();
+
+        let errs = concat_streams(vec![(0, first), (usize::MAX, second)]).unwrap_err();
+        assert_eq!(errs, vec![(TokenPosition { line: 1, position: 0, length: 1 }, ScanError::PositionOverflow)]);
+    }
+}
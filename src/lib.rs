@@ -0,0 +1,3 @@
+extern crate regex;
+
+pub mod lang;